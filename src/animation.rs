@@ -0,0 +1,165 @@
+use bevy::{prelude::*, reflect::TypeUuid};
+use serde::Deserialize;
+
+use crate::{
+    ron_asset::{RonAsset, RonLoader},
+    movement::Velocity,
+    collisions::Grounded};
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<AnimationSet>()
+            .init_asset_loader::<RonLoader<AnimationSet>>()
+            .add_system(choose_animation.label("choose-animation"))
+            .add_system(animate_sprites.after("choose-animation"));
+    }
+}
+
+/// What a character is doing, each state plays its own clip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationState {
+    Idle,
+    Run,
+    Jump,
+    Fall,
+    Land
+}
+
+/// Frames from the character's atlas played in order
+#[derive(Debug, Clone, Deserialize)]
+pub struct Clip {
+    pub frames: Vec<usize>,
+    pub fps: f32,
+    /// clips that don't loop hold their last frame
+    pub looping: bool
+}
+
+impl Clip {
+    fn length(&self) -> f32 {
+        self.frames.len() as f32 / self.fps
+    }
+
+    fn frame_at(&self, elapsed: f32) -> usize {
+        if self.frames.is_empty() {
+            return 0;
+        }
+        let step = (elapsed * self.fps) as usize;
+        let step = if self.looping { step % self.frames.len() } else { step.min(self.frames.len() - 1) };
+        self.frames[step]
+    }
+}
+
+/// The clips for one character, loaded from a `.anim.ron` file so several characters can share
+/// the same animation logic with different frames
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "edd1c174-08a6-4e94-9f48-7d3079add863"]
+pub struct AnimationSet {
+    pub idle: Clip,
+    pub run: Clip,
+    pub jump: Clip,
+    pub fall: Clip,
+    pub land: Clip,
+    /// horizontal speed above which the character counts as running and turns to face its motion
+    pub run_threshold: f32,
+    /// set when the frames are drawn facing left rather than right
+    pub faces_left: bool
+}
+
+impl RonAsset for AnimationSet {
+    const EXTENSIONS: &'static [&'static str] = &["anim.ron"];
+}
+
+impl AnimationSet {
+    pub fn clip(&self, state: AnimationState) -> &Clip {
+        match state {
+            AnimationState::Idle => &self.idle,
+            AnimationState::Run => &self.run,
+            AnimationState::Jump => &self.jump,
+            AnimationState::Fall => &self.fall,
+            AnimationState::Land => &self.land
+        }
+    }
+}
+
+/// Plays the clips of an [`AnimationSet`] on a sprite from the character's movement
+#[derive(Component)]
+pub struct Animator {
+    set: Handle<AnimationSet>,
+    state: AnimationState,
+    elapsed: f32,
+    facing_left: bool
+}
+
+impl Animator {
+    pub fn new(set: Handle<AnimationSet>) -> Self {
+        Animator {
+            set,
+            state: AnimationState::Idle,
+            elapsed: 0.,
+            facing_left: false
+        }
+    }
+
+    fn set_state(&mut self, state: AnimationState) {
+        if self.state != state {
+            self.state = state;
+            self.elapsed = 0.;
+        }
+    }
+}
+
+/// Picks the next state from the current one, landing plays once before going back to idle or running
+fn next_state(current: AnimationState, finished: bool, on_ground: bool, velocity: Vec2, run_threshold: f32) -> AnimationState {
+    if !on_ground {
+        return if velocity.y > 0. { AnimationState::Jump } else { AnimationState::Fall };
+    }
+    match current {
+        AnimationState::Jump | AnimationState::Fall => AnimationState::Land,
+        AnimationState::Land if !finished => AnimationState::Land,
+        _ if velocity.x.abs() > run_threshold => AnimationState::Run,
+        _ => AnimationState::Idle
+    }
+}
+
+fn choose_animation(
+    sets: Res<Assets<AnimationSet>>,
+    mut animators: Query<(&mut Animator, &Velocity, Option<&Grounded>)>
+) {
+    for (mut animator, velocity, grounded) in animators.iter_mut() {
+        let set = match sets.get(&animator.set) {
+            Some(set) => set,
+            None => continue
+        };
+        //things without a grounded check are treated as always standing
+        let on_ground = grounded.map_or(true, |g| g.on_ground);
+        let finished = animator.elapsed >= set.clip(animator.state).length();
+        let state = next_state(animator.state, finished, on_ground, velocity.value, set.run_threshold);
+        animator.set_state(state);
+
+        //keep facing the same way when stopped
+        if velocity.value.x > set.run_threshold {
+            animator.facing_left = false;
+        } else if velocity.value.x < -set.run_threshold {
+            animator.facing_left = true;
+        }
+    }
+}
+
+fn animate_sprites(
+    time: Res<Time>,
+    sets: Res<Assets<AnimationSet>>,
+    mut animators: Query<(&mut Animator, &mut TextureAtlasSprite)>
+) {
+    for (mut animator, mut sprite) in animators.iter_mut() {
+        let set = match sets.get(&animator.set) {
+            Some(set) => set,
+            None => continue
+        };
+        animator.elapsed += time.delta_seconds();
+        sprite.index = set.clip(animator.state).frame_at(animator.elapsed);
+        sprite.flip_x = animator.facing_left != set.faces_left;
+    }
+}
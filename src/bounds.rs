@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+
+use crate::{
+  TILE_SIZE,
+  player::Player,
+  collisions::Collidable,
+  tilemap::MapInfo,
+  geometry::AxisAlignedBoundingBox,
+  respawn::{RespawnEvent, Respawning}};
+
+pub struct BoundsPlugin;
+
+impl Plugin for BoundsPlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .add_event::<OutOfBounds>()
+        .add_system(check_bounds.label("bounds"))
+        .add_system(handle_out_of_bounds.after("bounds").before("respawn-requests"));
+  }
+}
+
+/// How far past the edge of the map a collidable can go before it counts as lost
+const BOUNDS_MARGIN: f32 = TILE_SIZE * 4.;
+
+/// Fired each frame a collidable is outside the map, the bottom edge acts as a kill plane
+pub struct OutOfBounds {
+  pub entity: Entity
+}
+
+#[allow(clippy::type_complexity)]
+fn check_bounds(
+  map: Option<Res<MapInfo>>,
+  collidables: Query<(Entity, &Transform), (With<Collidable>, Without<Respawning>)>,
+  mut events: EventWriter<OutOfBounds>
+) {
+  let map = match map {
+    Some(map) => map,
+    None => return
+  };
+  let bounds = &map.bounds;
+  let allowed = AxisAlignedBoundingBox::new(bounds.center(), bounds.size() + Vec2::splat(BOUNDS_MARGIN * 2.));
+  for (entity, transform) in collidables.iter() {
+    if !allowed.contains(transform.translation.truncate()) {
+      events.send(OutOfBounds { entity });
+    }
+  }
+}
+
+/// Players go back to their checkpoint, anything else is gone for good
+fn handle_out_of_bounds(
+  mut commands: Commands,
+  mut events: EventReader<OutOfBounds>,
+  players: Query<(), With<Player>>,
+  mut respawns: EventWriter<RespawnEvent>
+) {
+  for event in events.iter() {
+    if players.get(event.entity).is_ok() {
+      respawns.send(RespawnEvent { entity: event.entity });
+    } else {
+      commands.entity(event.entity).despawn_recursive();
+    }
+  }
+}
@@ -0,0 +1,192 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_inspector_egui::Inspectable;
+
+use crate::{player::Player, movement::{Interpolated, Velocity}, tilemap::MapInfo, geometry::AxisAlignedBoundingBox};
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(leash_players.after("player-movement"))
+            .add_system_to_stage(CoreStage::PostUpdate, camera_follow.after("interpolate").before(TransformSystem::TransformPropagate));
+    }
+}
+
+/// Framing for the one camera every player shares.
+/// It zooms out as players spread apart, and once it can't zoom any further a straggler is
+/// teleported back to the nearest other player.
+#[derive(Component, Inspectable)]
+pub struct SharedCamera {
+    /// space kept between the players and the edge of the screen, in pixels
+    pub margin: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// how quickly the zoom eases towards what the players need, per second
+    pub zoom_speed: f32
+}
+
+impl Default for SharedCamera {
+    fn default() -> Self {
+        SharedCamera {
+            margin: 48.0,
+            min_zoom: 1.0,
+            max_zoom: 2.0,
+            zoom_speed: 3.0
+        }
+    }
+}
+
+impl SharedCamera {
+    /// Scale needed to fit `size` plus the margin into a view that is `view` across at scale 1
+    fn zoom_to_fit(&self, size: Vec2, view: Vec2) -> f32 {
+        let needed = (size + Vec2::splat(self.margin * 2.0)) / view;
+        needed.x.max(needed.y).clamp(self.min_zoom, self.max_zoom)
+    }
+}
+
+/// How the camera chases the players it frames
+#[derive(Component, Inspectable)]
+pub struct CameraController {
+    /// half size of the box around the camera's focus the players can move in without it following
+    pub dead_zone: Vec2,
+    /// how quickly the camera catches up with its target, per second
+    pub damping: f32,
+    /// seconds of the players' velocity the camera looks ahead by
+    pub look_ahead: f32,
+    /// furthest the look-ahead can shift the camera, in pixels
+    pub max_look_ahead: f32,
+    focus: Vec2
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController {
+            dead_zone: Vec2::new(24.0, 32.0),
+            damping: 6.0,
+            look_ahead: 0.4,
+            max_look_ahead: 64.0,
+            focus: Vec2::ZERO
+        }
+    }
+}
+
+impl CameraController {
+    /// Drags the focus along just far enough to keep `target` inside the dead zone
+    fn track(&mut self, target: Vec2) {
+        let offset = target - self.focus;
+        let outside = offset.abs() - self.dead_zone;
+        self.focus += outside.max(Vec2::ZERO) * offset.signum();
+    }
+}
+
+/// Keeps a view of `half_view` half size inside the map, centring on any axis where the map is
+/// smaller than the view
+fn clamp_to_map(position: Vec2, half_view: Vec2, bounds: &AxisAlignedBoundingBox) -> Vec2 {
+    let min = bounds.min() + half_view;
+    let max = bounds.max() - half_view;
+    let centre = bounds.center();
+    Vec2::new(
+        if min.x > max.x { centre.x } else { position.x.clamp(min.x, max.x) },
+        if min.y > max.y { centre.y } else { position.y.clamp(min.y, max.y) })
+}
+
+/// Unscaled size of the area an orthographic projection shows
+fn view_size(projection: &OrthographicProjection) -> Vec2 {
+    Vec2::new(projection.right - projection.left, projection.top - projection.bottom)
+}
+
+/// Smallest box around every player as (min, max), or `None` with no players
+fn player_bounds(positions: impl Iterator<Item = Vec2>) -> Option<(Vec2, Vec2)> {
+    positions.fold(None, |bounds, p| match bounds {
+        None => Some((p, p)),
+        Some((min, max)) => Some((min.min(p), max.max(p)))
+    })
+}
+
+fn camera_follow(
+    time: Res<Time>,
+    mut started: Local<bool>,
+    map: Option<Res<MapInfo>>,
+    player_query: Query<(&Transform, &Velocity), With<Player>>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection, &SharedCamera, &mut CameraController), Without<Player>>
+) {
+    let (min, max) = match player_bounds(player_query.iter().map(|(t, _)| t.translation.truncate())) {
+        Some(bounds) => bounds,
+        None => return
+    };
+    let (mut camera_transform, mut projection, camera, mut controller) = match camera_query.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return
+    };
+    let dt = time.delta_seconds();
+
+    let zoom = camera.zoom_to_fit(max - min, view_size(&projection));
+    let blend = 1.0 - (-camera.zoom_speed * dt).exp();
+    projection.scale += (zoom - projection.scale) * blend;
+
+    let centre = (min + max) / 2.0;
+    let count = player_query.iter().count() as f32;
+    let velocity = player_query.iter().fold(Vec2::ZERO, |total, (_, v)| total + v.value) / count;
+    //the first frame with players snaps straight to them instead of sliding in from the origin
+    if !*started {
+        controller.focus = centre;
+    }
+    controller.track(centre);
+    let ahead = (velocity * controller.look_ahead).clamp_length_max(controller.max_look_ahead);
+    let mut target = controller.focus + ahead;
+    if let Some(map) = map {
+        target = clamp_to_map(target, view_size(&projection) * projection.scale / 2.0, &map.bounds);
+    }
+
+    let current = camera_transform.translation.truncate();
+    let position = if *started {
+        current + (target - current) * (1.0 - (-controller.damping * dt).exp())
+    } else {
+        target
+    };
+    camera_transform.translation.x = position.x;
+    camera_transform.translation.y = position.y;
+    *started = true;
+}
+
+/// Teleports whoever is furthest from the group back to their nearest partner when the players
+/// can't all fit on screen at maximum zoom
+fn leash_players(
+    mut players: Query<(Entity, &mut Transform, &mut Interpolated, &mut Velocity), With<Player>>,
+    camera_query: Query<(&OrthographicProjection, &SharedCamera)>
+) {
+    let (projection, camera) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return
+    };
+    let positions: Vec<(Entity, Vec2)> = players.iter()
+        .map(|(entity, _, interpolated, _)| (entity, interpolated.position()))
+        .collect();
+    let (min, max) = match player_bounds(positions.iter().map(|(_, p)| *p)) {
+        Some(bounds) => bounds,
+        None => return
+    };
+    let visible = view_size(projection) * camera.max_zoom - Vec2::splat(camera.margin * 2.0);
+    let spread = max - min;
+    if spread.x <= visible.x && spread.y <= visible.y {
+        return;
+    }
+
+    let centre = positions.iter().fold(Vec2::ZERO, |total, (_, p)| total + *p) / positions.len() as f32;
+    let straggler = positions.iter()
+        .max_by(|(_, a), (_, b)| a.distance_squared(centre).partial_cmp(&b.distance_squared(centre)).unwrap());
+    let (straggler, from) = match straggler {
+        Some(straggler) => *straggler,
+        None => return
+    };
+    let partner = positions.iter()
+        .filter(|(entity, _)| *entity != straggler)
+        .min_by(|(_, a), (_, b)| a.distance_squared(from).partial_cmp(&b.distance_squared(from)).unwrap());
+    if let Some((_, to)) = partner {
+        if let Ok((_, mut transform, mut interpolated, mut velocity)) = players.get_mut(straggler) {
+            interpolated.teleport(&mut transform, *to);
+            velocity.value = Vec2::ZERO;
+        }
+    }
+}
@@ -1,20 +1,125 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
 use bevy_inspector_egui::Inspectable;
 
-use crate::{movement::{Velocity, Accelleration}, tilemap::TileGrid};
+use crate::{
+  movement::{Velocity, Accelleration, PhysicsBody, PhysicalUniverse, PHYSICS_STAGE, PHYSICS_DT},
+  tilemap::{TileGrid, TileSurface},
+  platforms::MovingPlatform,
+  geometry::{AxisAlignedBoundingBox, Shape, Circle, Capsule},
+  TILE_SIZE};
 
 pub struct CollisionsPlugin;
 
 impl Plugin for CollisionsPlugin {
   fn build(&self, app:&mut App) {
       app
-        .add_system(collisions.label("collisions").after("velocity").before("movement"));
+        .add_event::<CollisionStarted>()
+        .add_event::<CollisionEnded>()
+        .init_resource::<Contacts>()
+        .add_system_to_stage(PHYSICS_STAGE, collisions.label("collisions").after("velocity").before("movement"))
+        .add_system_to_stage(PHYSICS_STAGE, entity_collisions.label("entity-collisions").after("movement"));
   }
 }
 
+/// Fired on the first frame two collidables overlap
+pub struct CollisionStarted {
+  pub a: Entity,
+  pub b: Entity
+}
+
+/// Fired on the first frame two collidables stop overlapping
+pub struct CollisionEnded {
+  pub a: Entity,
+  pub b: Entity
+}
+
+/// Pairs of collidables overlapping as of the last check, lowest entity first
+#[derive(Default)]
+pub struct Contacts {
+  pairs: HashSet<(Entity, Entity)>
+}
+
+impl Contacts {
+  /// Every pair of collidables currently overlapping
+  pub fn pairs(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+    self.pairs.iter().copied()
+  }
+}
+
+fn ordered(a: Entity, b: Entity) -> (Entity, Entity) {
+  if a < b { (a, b) } else { (b, a) }
+}
+
+/// What a collidable was standing on after the last collision pass
+#[derive(Component, Default)]
+pub struct Grounded {
+  pub on_ground: bool,
+  pub platform: Option<Entity>,
+  /// the tile underfoot, if it has special friction
+  pub surface: Option<TileSurface>
+}
+
 #[derive(Component, Inspectable)]
 pub struct Collidable {
-  pub size: f32
+  pub size: f32,
+  pub shape: CollisionShape,
+  pub groups: CollisionGroups
+}
+
+/// Bitfield collision groups.
+/// Two things interact only when each one's layer is in the other's mask.
+/// Bits past `PLAYER` are free for maps to use, for example 4 for enemies and 8 for pickups.
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq)]
+pub struct CollisionGroups {
+  pub layer: u32,
+  pub mask: u32
+}
+
+impl CollisionGroups {
+  pub const WORLD: u32 = 1;
+  pub const PLAYER: u32 = 1 << 1;
+  pub const ALL: u32 = u32::MAX;
+
+  pub fn new(layer: u32, mask: u32) -> Self {
+    CollisionGroups { layer, mask }
+  }
+
+  pub fn interacts(&self, other: &CollisionGroups) -> bool {
+    self.layer & other.mask != 0 && other.layer & self.mask != 0
+  }
+}
+
+impl Default for CollisionGroups {
+  fn default() -> Self {
+    CollisionGroups::new(CollisionGroups::WORLD, CollisionGroups::ALL)
+  }
+}
+
+/// Every shape fits inside a `size` by `size` square centered on the entity
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq)]
+pub enum CollisionShape {
+  Square,
+  Circle,
+  /// upright capsule, two thirds as wide as it is tall
+  Capsule
+}
+
+impl Collidable {
+  pub fn new(size: f32) -> Self {
+    Collidable { size, shape: CollisionShape::Square, groups: CollisionGroups::default() }
+  }
+
+  pub fn with_groups(self, layer: u32, mask: u32) -> Self {
+    Collidable { groups: CollisionGroups::new(layer, mask), ..self }
+  }
+
+  pub fn shape_at(&self, center: Vec2) -> Shape {
+    match self.shape {
+      CollisionShape::Square => Shape::Box(AxisAlignedBoundingBox::new(center, Vec2::splat(self.size))),
+      CollisionShape::Circle => Shape::Circle(Circle::new(center, self.size / 2.)),
+      CollisionShape::Capsule => Shape::Capsule(Capsule::vertical(center, self.size * 2. / 3., self.size))
+    }
+  }
 }
 
 enum Direction {
@@ -66,45 +171,169 @@ fn ray_origins(center: Vec3, size: f32, motion: Vec2) -> Vec<Vec2> {
   }
 }
 
+/// Round shapes can't use corner rays, so they check whether the moved shape would overlap a tile
+fn blocked(grid: &TileGrid, shape: &Shape, motion: Vec2) -> Option<IVec2> {
+  grid.overlaps(&shape.translate(motion)).filter(|_| grid.overlaps(shape).is_none())
+}
+
+/// Moving platforms can be stood on but jumped through from below
+const PLATFORM_TOLERANCE: f32 = 2.;
+
+/// Velocity along an axis after hitting something, reversed and scaled by bounciness
+fn bounce(v: f32, bounciness: f32) -> f32 {
+  -v * bounciness
+}
+
+#[allow(clippy::type_complexity)]
 fn collisions(
-  mut collidables: Query<(&Transform, &mut Velocity, &mut Accelleration, &Collidable)>,
-  tile_maps: Query<(&TileGrid, &Name)>,
-  time: Res<Time>
+  mut collidables: Query<(&Transform, &mut Velocity, &mut Accelleration, &Collidable, Option<&mut Grounded>, Option<&PhysicsBody>)>,
+  tile_maps: Query<&TileGrid>,
+  platforms: Query<(Entity, &Transform, &MovingPlatform)>,
+  universe: Res<PhysicalUniverse>
 ) {
-  for (position, mut velocity, mut accel, collidable) in collidables.iter_mut() {
+  for (position, mut velocity, mut accel, collidable, grounded, body) in collidables.iter_mut() {
+    let bounciness = body.map_or(universe.default_body().bounciness, |b| b.bounciness);
     let p = position.translation;
-    let ds = time.delta_seconds();
-    for (grid, name) in tile_maps.iter() {
-      if name.starts_with("ground") {
-        //y collisions
-        if velocity.value.y != 0. {
-          for ray_origin in ray_origins(p, collidable.size, Vec2::new(0., velocity.value.y)) {
-            let hit = grid.cast_axis_ray(ray_origin, Vec2::new(0., velocity.value.y * ds));
-            match hit {
-              Some(_v) => {
-                //TODO: calculate distance to actual intersection then subtract that from the velocity
-                velocity.value.y = 0.;
-                accel.clear_y();
-              },
-              None => {}
-            }
+    let ds = PHYSICS_DT;
+    let mut on_ground = false;
+    let mut platform_under = None;
+    let mut surface = None;
+    //hits are gathered over every layer first, so each axis bounces at most once a step
+    let mut hit_y = None;
+    let mut hit_x = None;
+    for grid in tile_maps.iter() {
+      if !grid.collides_with(&collidable.groups) {
+        continue;
+      }
+      if collidable.shape != CollisionShape::Square {
+        let shape = collidable.shape_at(p.truncate());
+        let hit = if velocity.value.y != 0. { blocked(grid, &shape, Vec2::new(0., velocity.value.y * ds)) } else { None };
+        if let Some(tile) = hit {
+          if velocity.value.y < 0. {
+            on_ground = true;
+            surface = surface.or(grid.surface(tile));
           }
+          hit_y = Some(Vec2::new(0., -velocity.value.y.signum()));
         }
-     
-        if velocity.value.x != 0. {
-          for ray_origin in ray_origins(p, collidable.size, Vec2::new(velocity.value.x * ds, 0.)) {
-            let hit = grid.cast_axis_ray(ray_origin, Vec2::new(velocity.value.x * ds, 0.));
-            match hit {
-              Some(_v) => {
-                //TODO: calculate distance to actual intersection then subtract that from the velocity
-                velocity.value.x = 0.;
-                accel.clear_x();
-              },
-              None => {}
+        if velocity.value.x != 0. && blocked(grid, &shape, Vec2::new(velocity.value.x * ds, 0.)).is_some() {
+          hit_x = Some(Vec2::new(-velocity.value.x.signum(), 0.));
+        }
+        continue;
+      }
+
+      //y collisions
+      if velocity.value.y != 0. {
+        for ray_origin in ray_origins(p, collidable.size, Vec2::new(0., velocity.value.y)) {
+          if let Some(v) = grid.cast_axis_ray(ray_origin, Vec2::new(0., velocity.value.y * ds)) {
+            if v.normal().y > 0. {
+              on_ground = true;
+              surface = surface.or(v.tile().and_then(|t| grid.surface(t)));
             }
+            hit_y = Some(v.normal());
           }
         }
       }
+   
+      if velocity.value.x != 0. {
+        for ray_origin in ray_origins(p, collidable.size, Vec2::new(velocity.value.x * ds, 0.)) {
+          if let Some(v) = grid.cast_axis_ray(ray_origin, Vec2::new(velocity.value.x * ds, 0.)) {
+            hit_x = Some(v.normal());
+          }
+        }
+      }
+    }
+
+    accel.clear_contacts();
+    //TODO: calculate distance to actual intersection then subtract that from the velocity
+    if let Some(normal) = hit_y {
+      velocity.value.y = bounce(velocity.value.y, bounciness);
+      accel.add_contact(normal);
+    }
+    if let Some(normal) = hit_x {
+      velocity.value.x = bounce(velocity.value.x, bounciness);
+      accel.add_contact(normal);
+    }
+
+    if velocity.value.y < 0. {
+      let bounds = collidable.shape_at(p.truncate()).bounds();
+      let fall = velocity.value.y * ds;
+      for (entity, transform, platform) in platforms.iter() {
+        if !platform.groups.interacts(&collidable.groups) {
+          continue;
+        }
+        let deck = platform.bounds(transform);
+        let top = deck.max().y;
+        let above = bounds.min().y >= top - PLATFORM_TOLERANCE;
+        let beside = bounds.max().x <= deck.min().x || bounds.min().x >= deck.max().x;
+        if above && !beside && bounds.min().y + fall <= top {
+          //land exactly on the deck rather than stopping short of it
+          velocity.value.y = (top - bounds.min().y) / ds;
+          accel.add_contact(Vec2::Y);
+          on_ground = true;
+          platform_under = Some(entity);
+          break;
+        }
+      }
+    }
+
+    if let Some(mut grounded) = grounded {
+      grounded.on_ground = on_ground;
+      grounded.platform = platform_under;
+      grounded.surface = surface;
     }
   }
+}
+
+const BROADPHASE_CELL: f32 = TILE_SIZE * 4.;
+
+fn broadphase_cell(p: Vec2) -> IVec2 {
+  IVec2::new((p.x / BROADPHASE_CELL).floor() as i32, (p.y / BROADPHASE_CELL).floor() as i32)
+}
+
+fn entity_collisions(
+  collidables: Query<(Entity, &Transform, &Collidable)>,
+  mut contacts: ResMut<Contacts>,
+  mut started: EventWriter<CollisionStarted>,
+  mut ended: EventWriter<CollisionEnded>
+) {
+  let shapes: Vec<(Entity, Shape, CollisionGroups)> = collidables.iter()
+    .map(|(entity, transform, collidable)|
+      (entity, collidable.shape_at(transform.translation.truncate()), collidable.groups))
+    .collect();
+
+  //broadphase, bucket every shape into each grid cell its bounds touch
+  let mut cells: HashMap<IVec2, Vec<usize>> = HashMap::default();
+  for (i, (_, shape, _)) in shapes.iter().enumerate() {
+    let bounds = shape.bounds();
+    let min = broadphase_cell(bounds.min());
+    let max = broadphase_cell(bounds.max());
+    for y in min.y..=max.y {
+      for x in min.x..=max.x {
+        cells.entry(IVec2::new(x, y)).or_default().push(i);
+      }
+    }
+  }
+
+  //narrowphase, only shapes sharing a cell can touch
+  let mut pairs = HashSet::default();
+  for bucket in cells.values() {
+    for (n, &i) in bucket.iter().enumerate() {
+      for &j in &bucket[n + 1..] {
+        let (a, shape_a, groups_a) = &shapes[i];
+        let (b, shape_b, groups_b) = &shapes[j];
+        let pair = ordered(*a, *b);
+        if !pairs.contains(&pair) && groups_a.interacts(groups_b) && shape_a.intersects(shape_b) {
+          pairs.insert(pair);
+        }
+      }
+    }
+  }
+
+  for &(a, b) in pairs.difference(&contacts.pairs) {
+    started.send(CollisionStarted{a, b});
+  }
+  for &(a, b) in contacts.pairs.difference(&pairs) {
+    ended.send(CollisionEnded{a, b});
+  }
+  contacts.pairs = pairs;
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
-use bevy_inspector_egui::{WorldInspectorPlugin, RegisterInspectable};
+use bevy_inspector_egui::{WorldInspectorPlugin, InspectorPlugin, RegisterInspectable};
 
-use crate::{player::Player, movement::{Velocity, Accelleration}, collisions::Collidable};
+use crate::{player::Player, movement::{Velocity, Accelleration, PhysicsBody, PhysicalUniverse}, collisions::{Collidable, CollisionStarted, CollisionEnded}, camera::{SharedCamera, CameraController}, health::Health, triggers::{TriggerEntered, TriggerExited}};
 
 pub struct DebugPlugin;
 
@@ -9,10 +9,40 @@ impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
         if cfg!(debug_assertions) {
             app.add_plugin(WorldInspectorPlugin::new())
+                .add_plugin(InspectorPlugin::<PhysicalUniverse>::new())
                 .register_inspectable::<Player>()
                 .register_inspectable::<Velocity>()
                 .register_inspectable::<Accelleration>()
-                .register_inspectable::<Collidable>();
+                .register_inspectable::<PhysicsBody>()
+                .register_inspectable::<Collidable>()
+                .register_inspectable::<SharedCamera>()
+                .register_inspectable::<CameraController>()
+                .register_inspectable::<Health>()
+                .add_system(log_collisions)
+                .add_system(log_triggers);
         }
     }
-}
\ No newline at end of file
+}
+
+fn log_collisions(
+    mut started: EventReader<CollisionStarted>,
+    mut ended: EventReader<CollisionEnded>
+) {
+    for event in started.iter() {
+        debug!("collision started between {:?} and {:?}", event.a, event.b);
+    }
+    for event in ended.iter() {
+        debug!("collision ended between {:?} and {:?}", event.a, event.b);
+    }
+}
+fn log_triggers(
+    mut entered: EventReader<TriggerEntered>,
+    mut exited: EventReader<TriggerExited>
+) {
+    for event in entered.iter() {
+        debug!("{:?} entered trigger {:?}", event.entity, event.trigger);
+    }
+    for event in exited.iter() {
+        debug!("{:?} left trigger {:?}", event.entity, event.trigger);
+    }
+}
@@ -10,6 +10,14 @@ impl Line {
     pub fn new(origin: Vec2, vector: Vec2) -> Self {
         Line{origin, vector}
     }
+
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    pub fn vector(&self) -> Vec2 {
+        self.vector
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -17,12 +25,37 @@ pub struct LineHit {
     line: Line,
     aabb: AxisAlignedBoundingBox,
     pos: Vec2,
-    t: f32
+    t: f32,
+    normal: Vec2,
+    tile: Option<IVec2>
 }
 
 impl LineHit {
-    pub fn new(line: Line, aabb: AxisAlignedBoundingBox, pos: Vec2, t: f32) -> Self {
-        LineHit{line, aabb, pos, t}
+    pub fn new(line: Line, aabb: AxisAlignedBoundingBox, pos: Vec2, t: f32, normal: Vec2) -> Self {
+        LineHit{line, aabb, pos, t, normal, tile: None}
+    }
+
+    /// Tags the hit with the grid coordinate of the tile that was struck
+    pub fn with_tile(self, tile: IVec2) -> Self {
+        LineHit{tile: Some(tile), ..self}
+    }
+
+    pub fn pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    pub fn t(&self) -> f32 {
+        self.t
+    }
+
+    /// Unit normal of the face that was hit, pointing back towards the ray origin
+    pub fn normal(&self) -> Vec2 {
+        self.normal
+    }
+
+    /// Grid coordinate of the hit tile, if the hit came from a tile grid
+    pub fn tile(&self) -> Option<IVec2> {
+        self.tile
     }
 
     pub fn _distance(&self) -> f32 {
@@ -39,7 +72,7 @@ pub struct AxisAlignedBoundingBox {
 }
 
 fn swap(a: f32, b:f32) -> (f32, f32) {
-    return (b, a);
+    (b, a)
 }
 
 impl AxisAlignedBoundingBox {
@@ -51,6 +84,13 @@ impl AxisAlignedBoundingBox {
         }
     }
 
+    pub fn min(&self) -> Vec2 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec2 {
+        self.max
+    }
 
     pub fn intersection(self, line: Line) -> Option<LineHit> {
         let origin = line.origin;
@@ -85,7 +125,12 @@ impl AxisAlignedBoundingBox {
             return None; 
         }
         
-        let t_intercept = if txmin < 0. {tymin} else {txmin};
+        //the ray enters the box when it has crossed into both slabs
+        let (t_intercept, normal) = if tymin > txmin {
+            (tymin, Vec2::new(0., -dir.y.signum()))
+        } else {
+            (txmin, Vec2::new(-dir.x.signum(), 0.))
+        };
         // println!("t_intercept: {}", t_intercept);
         if t_intercept > 1. {
             return None;
@@ -94,7 +139,271 @@ impl AxisAlignedBoundingBox {
         if t_intercept < 0. {
             return None;
         }
-        return Some(LineHit::new(line, self,
-            origin + dir * t_intercept, t_intercept));
+        Some(LineHit::new(line, self,
+            origin + dir * t_intercept, t_intercept, normal))
+    }
+
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x > self.min.x && point.x < self.max.x && point.y > self.min.y && point.y < self.max.y
+    }
+
+    /// Overlap test, boxes that only share an edge don't count
+    pub fn intersects(&self, other: &AxisAlignedBoundingBox) -> bool {
+        self.min.x < other.max.x && self.max.x > other.min.x && self.min.y < other.max.y && self.max.y > other.min.y
+    }
+
+    pub fn closest_point(&self, point: Vec2) -> Vec2 {
+        point.clamp(self.min, self.max)
+    }
+
+    pub fn corners(&self) -> [Vec2; 4] {
+        [
+            self.min,
+            Vec2::new(self.max.x, self.min.y),
+            self.max,
+            Vec2::new(self.min.x, self.max.y)
+        ]
+    }
+
+    /// Shortest distance between the box and the segment a-b, zero if they touch
+    pub fn distance_to_segment(&self, a: Vec2, b: Vec2) -> f32 {
+        if self.contains(a) || self.contains(b) || self.intersection(Line::new(a, b - a)).is_some() {
+            return 0.;
+        }
+        //once we know they're apart, the closest pair always involves a vertex of one or the other
+        let mut d = (self.closest_point(a) - a).length().min((self.closest_point(b) - b).length());
+        for corner in self.corners() {
+            d = d.min(point_segment_distance(corner, a, b));
+        }
+        d
+    }
+}
+
+fn closest_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b - a;
+    let len_sq = ab.length_squared();
+    if len_sq == 0. {
+        return a;
+    }
+    let t = ((p - a).dot(ab) / len_sq).clamp(0., 1.);
+    a + ab * t
+}
+
+pub fn point_segment_distance(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    (p - closest_on_segment(p, a, b)).length()
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Shortest distance between segments a1-b1 and a2-b2, zero if they cross
+pub fn segment_segment_distance(a1: Vec2, b1: Vec2, a2: Vec2, b2: Vec2) -> f32 {
+    let d1 = b1 - a1;
+    let d2 = b2 - a2;
+    let denom = cross(d1, d2);
+    if denom != 0. {
+        let s = cross(a2 - a1, d2) / denom;
+        let t = cross(a2 - a1, d1) / denom;
+        if (0. ..=1.).contains(&s) && (0. ..=1.).contains(&t) {
+            return 0.;
+        }
+    }
+    point_segment_distance(a1, a2, b2)
+        .min(point_segment_distance(b1, a2, b2))
+        .min(point_segment_distance(a2, a1, b1))
+        .min(point_segment_distance(b2, a1, b1))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Circle {
+    center: Vec2,
+    radius: f32
+}
+
+impl Circle {
+    pub fn new(center: Vec2, radius: f32) -> Self {
+        Circle{center, radius}
+    }
+
+    pub fn intersects_aabb(&self, aabb: &AxisAlignedBoundingBox) -> bool {
+        (aabb.closest_point(self.center) - self.center).length_squared() < self.radius * self.radius
+    }
+
+    /// Sweeps a box of the given size along the line, a zero size casts a plain ray.
+    /// The normal points out from the center, so it follows the curve.
+    pub fn intersection(&self, line: Line, size: Vec2) -> Option<LineHit> {
+        Capsule::new(self.center, self.center, self.radius).intersection(line, size)
+    }
+}
+
+/// A line segment a-b swept by a radius, like a pill
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Capsule {
+    a: Vec2,
+    b: Vec2,
+    radius: f32
+}
+
+impl Capsule {
+    pub fn new(a: Vec2, b: Vec2, radius: f32) -> Self {
+        Capsule{a, b, radius}
+    }
+
+    /// An upright capsule that fits inside a box of the given width and height
+    pub fn vertical(center: Vec2, width: f32, height: f32) -> Self {
+        let radius = width / 2.;
+        let half = (height / 2. - radius).max(0.);
+        Capsule::new(center - Vec2::new(0., half), center + Vec2::new(0., half), radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &AxisAlignedBoundingBox) -> bool {
+        aabb.distance_to_segment(self.a, self.b) < self.radius
+    }
+
+    /// Sweeps a box of the given size along the line, a zero size casts a plain ray.
+    /// The capsule grown by the box is covered by capsules around each box edge swept from a to b,
+    /// so the first of those the line enters is where it enters the whole shape.
+    pub fn intersection(&self, line: Line, size: Vec2) -> Option<LineHit> {
+        let corners = AxisAlignedBoundingBox::new(Vec2::ZERO, size).corners();
+        let mut closest: Option<(f32, Vec2)> = None;
+        for i in 0..4 {
+            let (k, next) = (corners[i], corners[(i + 1) % 4]);
+            for (a, b) in [(self.a + k, self.b + k), (self.a + k, self.a + next), (self.b + k, self.b + next)] {
+                if let Some(hit) = line_capsule(line, a, b, self.radius) {
+                    if closest.map_or(true, |(t, _)| hit.0 < t) {
+                        closest = Some(hit);
+                    }
+                }
+            }
+        }
+        let bounds = Shape::Capsule(*self).bounds();
+        let grown = AxisAlignedBoundingBox::new(bounds.center(), bounds.size() + size);
+        closest.map(|(t, normal)| LineHit::new(line, grown, line.origin + line.vector * t, t, normal))
+    }
+}
+
+/// Where the line enters the circle and the outward normal there.
+/// Lines starting inside the circle don't count, like `AxisAlignedBoundingBox::intersection`.
+fn line_circle(line: Line, center: Vec2, radius: f32) -> Option<(f32, Vec2)> {
+    let offset = line.origin - center;
+    let a = line.vector.length_squared();
+    let b = offset.dot(line.vector);
+    let c = offset.length_squared() - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0. || radius <= 0. || discriminant <= 0. {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    if !(0. ..=1.).contains(&t) {
+        return None;
     }
-}
\ No newline at end of file
+    Some((t, (line.origin + line.vector * t - center) / radius))
+}
+
+/// Fraction along the line where it crosses the segment a-b
+fn line_segment(line: Line, a: Vec2, b: Vec2) -> Option<f32> {
+    let edge = b - a;
+    let denom = cross(line.vector, edge);
+    if denom == 0. {
+        return None;
+    }
+    let t = cross(a - line.origin, edge) / denom;
+    let s = cross(a - line.origin, line.vector) / denom;
+    if (0. ..=1.).contains(&t) && (0. ..=1.).contains(&s) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Where the line enters the capsule a-b: either round end, or one of the two flat sides
+fn line_capsule(line: Line, a: Vec2, b: Vec2, radius: f32) -> Option<(f32, Vec2)> {
+    let mut hits = vec![line_circle(line, a, radius), line_circle(line, b, radius)];
+    let axis = (b - a).normalize_or_zero();
+    if axis != Vec2::ZERO {
+        for side in [axis.perp(), -axis.perp()] {
+            //only the side facing the line can be entered
+            if line.vector.dot(side) < 0. {
+                let offset = side * radius;
+                hits.push(line_segment(line, a + offset, b + offset).map(|t| (t, side)));
+            }
+        }
+    }
+    hits.into_iter().flatten().min_by(|x, y| x.0.partial_cmp(&y.0).unwrap())
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Shape {
+    Box(AxisAlignedBoundingBox),
+    Circle(Circle),
+    Capsule(Capsule)
+}
+
+impl Shape {
+    pub fn bounds(&self) -> AxisAlignedBoundingBox {
+        match self {
+            Shape::Box(aabb) => *aabb,
+            Shape::Circle(c) => AxisAlignedBoundingBox::new(c.center, Vec2::splat(c.radius * 2.)),
+            Shape::Capsule(c) => AxisAlignedBoundingBox{
+                min: c.a.min(c.b) - Vec2::splat(c.radius),
+                max: c.a.max(c.b) + Vec2::splat(c.radius)
+            }
+        }
+    }
+
+    pub fn translate(&self, by: Vec2) -> Shape {
+        match self {
+            Shape::Box(aabb) => Shape::Box(AxisAlignedBoundingBox{min: aabb.min + by, max: aabb.max + by}),
+            Shape::Circle(c) => Shape::Circle(Circle::new(c.center + by, c.radius)),
+            Shape::Capsule(c) => Shape::Capsule(Capsule::new(c.a + by, c.b + by, c.radius))
+        }
+    }
+
+    pub fn intersects_aabb(&self, aabb: &AxisAlignedBoundingBox) -> bool {
+        match self {
+            Shape::Box(b) => b.intersects(aabb),
+            Shape::Circle(c) => c.intersects_aabb(aabb),
+            Shape::Capsule(c) => c.intersects_aabb(aabb)
+        }
+    }
+
+    /// Sweeps a box of the given size along the line and returns where it first touches the shape
+    pub fn intersection(&self, line: Line, size: Vec2) -> Option<LineHit> {
+        match self {
+            Shape::Box(b) => AxisAlignedBoundingBox::new(b.center(), b.size() + size).intersection(line),
+            Shape::Circle(c) => c.intersection(line, size),
+            Shape::Capsule(c) => c.intersection(line, size)
+        }
+    }
+
+    /// Narrowphase overlap test between any two shapes.
+    /// Circles are treated as capsules whose segment has collapsed to a point.
+    pub fn intersects(&self, other: &Shape) -> bool {
+        match (self, other) {
+            (Shape::Box(a), _) => other.intersects_aabb(a),
+            (_, Shape::Box(b)) => self.intersects_aabb(b),
+            _ => {
+                let (a1, b1, r1) = self.segment();
+                let (a2, b2, r2) = other.segment();
+                segment_segment_distance(a1, b1, a2, b2) < r1 + r2
+            }
+        }
+    }
+
+    fn segment(&self) -> (Vec2, Vec2, f32) {
+        match self {
+            Shape::Box(b) => (b.center(), b.center(), 0.),
+            Shape::Circle(c) => (c.center, c.center, c.radius),
+            Shape::Capsule(c) => (c.a, c.b, c.radius)
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::Inspectable;
+use tiled::Properties;
+
+use crate::{
+  movement::Accelleration,
+  collisions::{Collidable, Contacts},
+  tilemap::{TileGrid, float_property, to_world},
+  geometry::AxisAlignedBoundingBox,
+  triggers::Trigger};
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .add_event::<DamageEvent>()
+        .add_event::<DeathEvent>()
+        .add_system(tile_hazards.label("hazards"))
+        .add_system(object_hazards.label("hazards"))
+        .add_system(contact_hazards.label("hazards"))
+        .add_system(apply_damage.label("damage").after("hazards"))
+        .add_system(flash_invulnerable.after("damage"));
+  }
+}
+
+/// How often a sprite blinks while invulnerable, per second
+const FLASH_RATE: f32 = 10.;
+/// Hazards always knock a little upwards so bodies don't get pinned against them
+const MIN_KNOCKBACK_LIFT: f32 = 0.5;
+
+/// Hit points, plus a spell of invulnerability after each hit
+#[derive(Component, Inspectable)]
+pub struct Health {
+  pub current: f32,
+  pub max: f32,
+  /// seconds of invulnerability after taking damage
+  pub invulnerability: f32,
+  invulnerable: f32
+}
+
+impl Health {
+  pub fn new(max: f32) -> Self {
+    Health { current: max, max, invulnerability: 1.5, invulnerable: 0. }
+  }
+
+  pub fn is_dead(&self) -> bool {
+    self.current <= 0.
+  }
+
+  pub fn is_invulnerable(&self) -> bool {
+    self.invulnerable > 0.
+  }
+
+  /// Back to full health with no invulnerability left over
+  pub fn revive(&mut self) {
+    self.current = self.max;
+    self.invulnerable = 0.;
+  }
+}
+
+/// Hurts anything with [`Health`] that touches it.
+/// Put on enemies directly, or loaded from trigger objects of type `hazard`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Hazard {
+  pub damage: f32,
+  /// strength of the impulse pushing the victim away
+  pub knockback: f32
+}
+
+impl Default for Hazard {
+  fn default() -> Self {
+    Hazard { damage: 1., knockback: 250. }
+  }
+}
+
+impl Hazard {
+  pub fn is_hazard_type(obj_type: &str) -> bool {
+    obj_type == "hazard"
+  }
+
+  /// `damage` and `knockback` object properties override the defaults
+  pub fn from_object(obj_type: &str, properties: &Properties) -> Option<Self> {
+    if !Hazard::is_hazard_type(obj_type) {
+      return None;
+    }
+    let hazard = Hazard::default();
+    Some(Hazard {
+      damage: float_property(properties, "damage").unwrap_or(hazard.damage),
+      knockback: float_property(properties, "knockback").unwrap_or(hazard.knockback)
+    })
+  }
+}
+
+/// Asks for `amount` of damage to `target`, knocking it away from `from`.
+/// Ignored while the target is invulnerable or already dead.
+pub struct DamageEvent {
+  pub target: Entity,
+  pub amount: f32,
+  pub from: Vec2,
+  pub knockback: f32
+}
+
+/// Fired once when an entity's health runs out
+pub struct DeathEvent {
+  pub entity: Entity
+}
+
+fn tile_hazards(
+  tile_maps: Query<&TileGrid>,
+  targets: Query<(Entity, &Transform, &Collidable, &Health)>,
+  mut damage: EventWriter<DamageEvent>
+) {
+  let knockback = Hazard::default().knockback;
+  for (entity, transform, collidable, health) in targets.iter() {
+    if health.is_invulnerable() || health.is_dead() {
+      continue;
+    }
+    //grown by a pixel so standing on a solid hazard tile counts as touching it
+    let bounds = collidable.shape_at(transform.translation.truncate()).bounds();
+    let probe = AxisAlignedBoundingBox::new(bounds.center(), bounds.size() + Vec2::splat(2.));
+    //hazard tiles follow the same collision groups as solid ones
+    let hazard = tile_maps.iter()
+      .filter(|tiles| tiles.collides_with(&collidable.groups))
+      .find_map(|tiles| tiles.hazard(&probe));
+    if let Some((tile, amount)) = hazard {
+      damage.send(DamageEvent { target: entity, amount, from: to_world(tile), knockback });
+    }
+  }
+}
+
+fn object_hazards(
+  hazards: Query<(&Trigger, &Hazard)>,
+  targets: Query<Entity, With<Health>>,
+  mut damage: EventWriter<DamageEvent>
+) {
+  for (trigger, hazard) in hazards.iter() {
+    for entity in targets.iter() {
+      if trigger.contains(entity) {
+        damage.send(DamageEvent { target: entity, amount: hazard.damage, from: trigger.center(), knockback: hazard.knockback });
+      }
+    }
+  }
+}
+
+fn contact_hazards(
+  contacts: Res<Contacts>,
+  hazards: Query<(&Hazard, &Transform)>,
+  targets: Query<(), With<Health>>,
+  mut damage: EventWriter<DamageEvent>
+) {
+  for (a, b) in contacts.pairs() {
+    for (source, target) in [(a, b), (b, a)] {
+      if let (Ok((hazard, transform)), Ok(())) = (hazards.get(source), targets.get(target)) {
+        damage.send(DamageEvent {
+          target,
+          amount: hazard.damage,
+          from: transform.translation.truncate(),
+          knockback: hazard.knockback
+        });
+      }
+    }
+  }
+}
+
+fn apply_damage(
+  mut events: EventReader<DamageEvent>,
+  mut targets: Query<(&mut Health, &Transform, Option<&mut Accelleration>)>,
+  mut deaths: EventWriter<DeathEvent>
+) {
+  for event in events.iter() {
+    let (mut health, transform, accel) = match targets.get_mut(event.target) {
+      Ok(target) => target,
+      Err(_) => continue
+    };
+    if health.is_invulnerable() || health.is_dead() {
+      continue;
+    }
+    health.current = (health.current - event.amount).max(0.);
+    health.invulnerable = health.invulnerability;
+    if health.is_dead() {
+      deaths.send(DeathEvent { entity: event.target });
+    }
+
+    if let Some(mut accel) = accel {
+      let mut away = (transform.translation.truncate() - event.from).normalize_or_zero();
+      away.y = away.y.max(MIN_KNOCKBACK_LIFT);
+      accel.add_impulse(away.normalize() * event.knockback);
+    }
+  }
+}
+
+fn flash_invulnerable(
+  time: Res<Time>,
+  mut targets: Query<(&mut Health, Option<&mut Visibility>)>
+) {
+  for (mut health, visibility) in targets.iter_mut() {
+    if health.is_invulnerable() {
+      health.invulnerable = (health.invulnerable - time.delta_seconds()).max(0.);
+    }
+    //always written so a revive part way through a flash leaves the sprite showing
+    if let Some(mut visibility) = visibility {
+      visibility.is_visible = !health.is_invulnerable() || (health.invulnerable * FLASH_RATE).fract() < 0.5;
+    }
+  }
+}
@@ -0,0 +1,257 @@
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashSet};
+use serde::Deserialize;
+
+use crate::ron_asset::{RonAsset, HotReload, HotReloadApp};
+
+pub struct InputActionsPlugin;
+
+impl Plugin for InputActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_hot_reload::<InputBindings>("controls.bindings.ron")
+            .init_resource::<InputBindings>()
+            .add_system(apply_bindings)
+            .add_system(read_actions.label("input"));
+    }
+}
+
+/// Things a player can do, independent of the key or button that does it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    Attack,
+    Pause
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Jump,
+        Action::Attack,
+        Action::Pause
+    ];
+}
+
+/// A gamepad axis driving a pair of opposite actions once it passes the threshold
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct AxisBinding {
+    pub axis: GamepadAxisType,
+    pub negative: Action,
+    pub positive: Action
+}
+
+/// Maps keys, gamepad buttons and gamepad axes to actions.
+/// Loaded from `controls.bindings.ron` and reloaded when that file changes, a key or button
+/// may drive several actions. Each keyboard scheme is a separate player, gamepads all share
+/// the button and axis bindings.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "c084a2ec-dc9c-45d7-bfea-f42c34b45215"]
+pub struct InputBindings {
+    pub keyboards: Vec<Vec<(KeyCode, Action)>>,
+    pub buttons: Vec<(GamepadButtonType, Action)>,
+    pub axes: Vec<AxisBinding>,
+    pub axis_threshold: f32,
+    /// stick travel ignored around the centre, as a fraction of full tilt
+    pub stick_deadzone: f32,
+    /// exponent applied to stick tilt past the deadzone, above 1 gives finer control at low speeds
+    pub stick_curve: f32
+}
+
+impl RonAsset for InputBindings {
+    const EXTENSIONS: &'static [&'static str] = &["bindings.ron"];
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        InputBindings {
+            keyboards: vec![
+                vec![
+                    (KeyCode::A, Action::MoveLeft),
+                    (KeyCode::D, Action::MoveRight),
+                    (KeyCode::W, Action::MoveUp),
+                    (KeyCode::S, Action::MoveDown),
+                    (KeyCode::Space, Action::Jump),
+                    (KeyCode::J, Action::Attack),
+                    (KeyCode::P, Action::Pause)
+                ],
+                vec![
+                    (KeyCode::Left, Action::MoveLeft),
+                    (KeyCode::Right, Action::MoveRight),
+                    (KeyCode::Up, Action::MoveUp),
+                    (KeyCode::Down, Action::MoveDown),
+                    (KeyCode::RShift, Action::Jump),
+                    (KeyCode::RControl, Action::Attack)
+                ]
+            ],
+            buttons: vec![
+                (GamepadButtonType::DPadLeft, Action::MoveLeft),
+                (GamepadButtonType::DPadRight, Action::MoveRight),
+                (GamepadButtonType::DPadUp, Action::MoveUp),
+                (GamepadButtonType::DPadDown, Action::MoveDown),
+                (GamepadButtonType::South, Action::Jump),
+                (GamepadButtonType::West, Action::Attack),
+                (GamepadButtonType::Start, Action::Pause)
+            ],
+            axes: vec![
+                AxisBinding{axis: GamepadAxisType::LeftStickX, negative: Action::MoveLeft, positive: Action::MoveRight},
+                AxisBinding{axis: GamepadAxisType::LeftStickY, negative: Action::MoveDown, positive: Action::MoveUp}
+            ],
+            axis_threshold: 0.5,
+            stick_deadzone: 0.15,
+            stick_curve: 1.5
+        }
+    }
+}
+
+impl InputBindings {
+    /// The keys of a keyboard scheme, empty if the scheme isn't defined
+    pub fn keys(&self, scheme: usize) -> &[(KeyCode, Action)] {
+        self.keyboards.get(scheme).map_or(&[][..], |keys| keys.as_slice())
+    }
+
+    /// The axis bound to a pair of opposite actions, and -1 if it's bound the other way round
+    pub fn axis_for(&self, negative: Action, positive: Action) -> Option<(GamepadAxisType, f32)> {
+        self.axes.iter().find_map(|binding| {
+            if (binding.negative, binding.positive) == (negative, positive) {
+                Some((binding.axis, 1.))
+            } else if (binding.negative, binding.positive) == (positive, negative) {
+                Some((binding.axis, -1.))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Maps a raw stick value to -1..1 after the deadzone and response curve
+    pub fn stick_response(&self, value: f32) -> f32 {
+        let live = 1. - self.stick_deadzone;
+        if live <= 0. {
+            return 0.;
+        }
+        let tilt = ((value.abs() - self.stick_deadzone) / live).clamp(0., 1.);
+        tilt.powf(self.stick_curve).copysign(value)
+    }
+}
+
+/// Per-player view of which actions are held, like `Input<KeyCode>` but for actions
+#[derive(Component, Default)]
+pub struct ActionState {
+    input: Input<Action>,
+    horizontal: f32,
+    vertical: f32
+}
+
+impl ActionState {
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.input.just_pressed(action)
+    }
+
+    /// How hard to move sideways from -1 to 1, full strength for keys and buttons and
+    /// scaled by tilt for the stick
+    pub fn horizontal(&self) -> f32 {
+        self.horizontal
+    }
+
+    /// How hard to move up or down from -1 to 1, like `horizontal`
+    pub fn vertical(&self) -> f32 {
+        self.vertical
+    }
+
+    fn update(&mut self, active: &HashSet<Action>) {
+        self.input.clear();
+        for action in Action::ALL {
+            if active.contains(&action) {
+                self.input.press(action);
+            } else {
+                self.input.release(action);
+            }
+        }
+    }
+}
+
+fn apply_bindings(
+    mut events: EventReader<AssetEvent<InputBindings>>,
+    file: Res<HotReload<InputBindings>>,
+    loaded: Res<Assets<InputBindings>>,
+    mut bindings: ResMut<InputBindings>
+) {
+    if let Some(file) = file.reloaded(&mut events, &loaded) {
+        *bindings = file.clone();
+    }
+}
+
+/// Where a player's actions come from
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSource {
+    /// index into the keyboard schemes of [`InputBindings`]
+    Keyboard(usize),
+    Gamepad(Gamepad)
+}
+
+fn read_actions(
+    mut players: Query<(&mut ActionState, &InputSource)>,
+    bindings: Res<InputBindings>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>
+) {
+    for (mut actions, source) in players.iter_mut() {
+        let mut active = HashSet::default();
+        let mut stick = Vec2::ZERO;
+        match *source {
+            InputSource::Keyboard(scheme) => {
+                for (key, action) in bindings.keys(scheme) {
+                    if keyboard.pressed(*key) {
+                        active.insert(*action);
+                    }
+                }
+            },
+            InputSource::Gamepad(gamepad) => {
+                for (button, action) in &bindings.buttons {
+                    if buttons.pressed(GamepadButton(gamepad, *button)) {
+                        active.insert(*action);
+                    }
+                }
+                //the stick is read from whichever axes the movement actions are bound to
+                let tilt = |negative, positive| bindings.axis_for(negative, positive)
+                    .and_then(|(axis, sign)| axes.get(GamepadAxis(gamepad, axis)).map(|value| value * sign))
+                    .unwrap_or(0.);
+                stick = Vec2::new(tilt(Action::MoveLeft, Action::MoveRight), tilt(Action::MoveDown, Action::MoveUp));
+            }
+        }
+
+        //digital input wins over the stick so keyboard always moves at full speed
+        let horizontal = match (active.contains(&Action::MoveLeft), active.contains(&Action::MoveRight)) {
+            (true, false) => -1.,
+            (false, true) => 1.,
+            _ => bindings.stick_response(stick.x)
+        };
+        let vertical = match (active.contains(&Action::MoveDown), active.contains(&Action::MoveUp)) {
+            (true, false) => -1.,
+            (false, true) => 1.,
+            _ => bindings.stick_response(stick.y)
+        };
+
+        if let InputSource::Gamepad(gamepad) = *source {
+            for binding in &bindings.axes {
+                let value = axes.get(GamepadAxis(gamepad, binding.axis)).unwrap_or(0.);
+                if value < -bindings.axis_threshold {
+                    active.insert(binding.negative);
+                } else if value > bindings.axis_threshold {
+                    active.insert(binding.positive);
+                }
+            }
+        }
+
+        actions.update(&active);
+        actions.horizontal = horizontal;
+        actions.vertical = vertical;
+    }
+}
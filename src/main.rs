@@ -12,6 +12,18 @@ mod tilemap;
 mod movement;
 mod collisions;
 mod geometry;
+mod raycast;
+mod triggers;
+mod platforms;
+mod universe;
+mod zones;
+mod ron_asset;
+mod input;
+mod camera;
+mod animation;
+mod health;
+mod respawn;
+mod bounds;
 
 use player::PlayerPlugin;
 use debug::DebugPlugin;
@@ -19,6 +31,16 @@ use spritesheet::SpriteSheetPlugin;
 use tilemap::TileMapPlugin;
 use movement::PhysicsMovementPlugin;
 use collisions::CollisionsPlugin;
+use triggers::TriggerPlugin;
+use platforms::PlatformPlugin;
+use universe::UniversePlugin;
+use zones::ZonePlugin;
+use input::InputActionsPlugin;
+use camera::{CameraPlugin, SharedCamera, CameraController};
+use animation::AnimationPlugin;
+use health::HealthPlugin;
+use respawn::RespawnPlugin;
+use bounds::BoundsPlugin;
 
 fn main() {
     let height = 700.0;
@@ -33,17 +55,33 @@ fn main() {
             ..Default::default()
         })
         .add_plugins(DefaultPlugins)
+        .add_startup_system_to_stage(StartupStage::PreStartup, watch_for_changes)
         .add_startup_system(spawn_camera)
+        .add_plugin(InputActionsPlugin)
         .add_plugin(SpriteSheetPlugin)
         .add_plugin(PlayerPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(AnimationPlugin)
         .add_plugin(TileMapPlugin)
         .add_plugin(PhysicsMovementPlugin)
+        .add_plugin(UniversePlugin)
         .add_plugin(CollisionsPlugin)
+        .add_plugin(TriggerPlugin)
+        .add_plugin(PlatformPlugin)
+        .add_plugin(ZonePlugin)
+        .add_plugin(HealthPlugin)
+        .add_plugin(RespawnPlugin)
+        .add_plugin(BoundsPlugin)
         .add_plugin(DebugPlugin)
         .add_system(bevy::input::system::exit_on_esc_system)
         .run();
 }
 
+/// Lets RON settings files be edited while the game is running
+fn watch_for_changes(assets: Res<AssetServer>) {
+    assets.watch_for_changes().unwrap();
+}
+
 fn spawn_camera(mut commands: Commands) {
     let mut camera = OrthographicCameraBundle::new_2d();
 
@@ -55,5 +93,7 @@ fn spawn_camera(mut commands: Commands) {
 
     camera.orthographic_projection.scaling_mode = ScalingMode::None;
 
-    commands.spawn_bundle(camera);
+    commands.spawn_bundle(camera)
+        .insert(SharedCamera::default())
+        .insert(CameraController::default());
 }
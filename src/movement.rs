@@ -1,8 +1,48 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, ecs::schedule::ShouldRun, transform::TransformSystem, reflect::TypeUuid};
 use bevy_inspector_egui::Inspectable;
+use serde::Deserialize;
 
+use crate::{collisions::Grounded, tilemap::float_property, zones::InZone};
+use tiled::Properties;
+
+/// Stage that runs all the physics systems on a fixed clock, after `Update` has gathered input
+pub const PHYSICS_STAGE: &str = "physics";
+
+/// How often the whole physics stage runs, triggers and entity events included.
+/// Twice the usual display rate, so fast movers don't skip through tiles.
+pub const PHYSICS_RATE: f64 = 120.;
+/// Seconds simulated by every run of the physics stage
+pub const PHYSICS_STEP: f64 = 1. / PHYSICS_RATE;
+pub const PHYSICS_DT: f32 = PHYSICS_STEP as f32;
+
+/// Banks frame time for the physics stage, which runs once per whole step of it.
+/// Does the job of bevy's `FixedTimestep`, but takes its time from `advance` so tests can feed it frames.
+/// There are no sub-steps inside a tick: the whole stage runs at `PHYSICS_RATE`,
+/// so triggers and entity events stay in step with movement and there's nothing left to subdivide.
+#[derive(Default)]
+pub struct PhysicsClock {
+  banked: f64
+}
+
+impl PhysicsClock {
+  pub fn advance(&mut self, seconds: f64) {
+    self.banked += seconds;
+  }
+
+  /// How far the banked time reaches into the next step, from 0 to 1
+  pub fn overstep(&self) -> f64 {
+    self.banked / PHYSICS_STEP
+  }
+}
+
+/// Global physics settings, loaded from `physics.universe.ron` with per-map overrides
+#[derive(Inspectable, Deserialize, TypeUuid, Debug, Clone)]
+#[uuid = "bd4693df-8e6b-42d8-8957-7d4c7113dcff"]
 pub struct PhysicalUniverse {
-  pub friction: f32,
+  /// horizontal and vertical friction while standing on something
+  pub ground_friction: Vec2,
+  /// horizontal and vertical friction in the air, vertical is low so gravity isn't damped away
+  pub air_friction: Vec2,
   pub gravity: f32,
   pub speed_of_light: f32,
   pub velocity_epsilon: f32
@@ -11,12 +51,60 @@ pub struct PhysicalUniverse {
 impl PhysicalUniverse {
   pub fn new() -> Self {
     PhysicalUniverse { 
-      friction: 10., 
+      ground_friction: Vec2::new(10., 10.),
+      air_friction: Vec2::new(4., 0.5),
       gravity: 1000.,
       speed_of_light: 200., 
       velocity_epsilon: 10. 
     }
   }
+
+  /// Applies the map's own physics properties on top of these settings
+  pub fn apply_overrides(&mut self, properties: &Properties) {
+    let apply = |key: &str, value: &mut f32| {
+      if let Some(v) = float_property(properties, key) {
+        *value = v;
+      }
+    };
+    apply("ground_friction_x", &mut self.ground_friction.x);
+    apply("ground_friction_y", &mut self.ground_friction.y);
+    apply("air_friction_x", &mut self.air_friction.x);
+    apply("air_friction_y", &mut self.air_friction.y);
+    apply("gravity", &mut self.gravity);
+    apply("speed_of_light", &mut self.speed_of_light);
+    apply("velocity_epsilon", &mut self.velocity_epsilon);
+  }
+
+  /// Body settings for anything that doesn't carry its own `PhysicsBody`
+  pub fn default_body(&self) -> PhysicsBody {
+    PhysicsBody {
+      gravity_scale: 1.,
+      mass: 1.,
+      ground_friction: self.ground_friction,
+      air_friction: self.air_friction,
+      max_speed: Vec2::splat(self.speed_of_light),
+      bounciness: 0.
+    }
+  }
+}
+
+impl Default for PhysicalUniverse {
+  fn default() -> Self {
+    PhysicalUniverse::new()
+  }
+}
+
+/// Per-entity physics settings, start from `PhysicalUniverse::default_body` and override what differs
+#[derive(Component, Inspectable, Debug, Clone, Copy)]
+pub struct PhysicsBody {
+  pub gravity_scale: f32,
+  /// forces and impulses are divided by mass, gravity isn't
+  pub mass: f32,
+  pub ground_friction: Vec2,
+  pub air_friction: Vec2,
+  pub max_speed: Vec2,
+  /// fraction of speed kept when bouncing off a surface
+  pub bounciness: f32
 }
 
 #[derive(Component, Inspectable)]
@@ -30,62 +118,139 @@ impl Velocity {
   }
 }
 
+/// Typed slots for the forces acting on a body, one force per kind
+#[derive(Inspectable, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForceKind {
+  PlayerAccel,
+  Wind,
+  /// kept up by wind zones, separate from `Wind` so zones never clear a passing gust
+  ZoneWind,
+  /// kept up by water zones
+  ZoneBuoyancy
+}
+
+impl Default for ForceKind {
+  fn default() -> Self {
+    ForceKind::PlayerAccel
+  }
+}
+
+#[derive(Inspectable, Default, Debug, Clone, Copy)]
+pub struct Force {
+  pub kind: ForceKind,
+  pub value: Vec2,
+  /// seconds until a timed force expires, infinite for continuous forces
+  pub remaining: f32
+}
+
 #[derive(Component, Inspectable)]
 pub struct Accelleration {
-  pub forces: Vec<(String, Vec2)>
+  forces: Vec<Force>,
+  impulse: Vec2,
+  /// normals of the surfaces the body was pressed against on the last collision pass
+  contacts: Vec<Vec2>
 }
 
 impl Accelleration {
   pub fn new() -> Self {
-    Accelleration { forces: vec![] }
+    Accelleration { forces: vec![], impulse: Vec2::ZERO, contacts: vec![] }
   }
 
+  /// Sum of the forces, less whatever pushes into a surface the body is touching
   pub fn value(&self) -> Vec2 {
     let mut v = Vec2::new(0.,0.);
-    for (_, force) in &self.forces {
-      v += *force;
+    for force in &self.forces {
+      v += force.value;
     }
-    return v;
+    self.resisted(v)
+  }
+
+  /// Sets a force that keeps acting until it's changed or removed
+  pub fn set_force(&mut self, kind: ForceKind, value: Vec2) {
+    self.set_timed_force(kind, value, f32::INFINITY);
   }
 
-  pub fn set_force(&mut self, name: &str, value: Vec2) {
-    let index = self.forces.iter().position(|(n, _)| n == name);
-    match index {
-      Some(i) => {
-        self.forces[i].1.x = value.x;
-        self.forces[i].1.y = value.y;
+  /// Sets a force that acts for the given number of seconds then expires
+  pub fn set_timed_force(&mut self, kind: ForceKind, value: Vec2, seconds: f32) {
+    match self.forces.iter_mut().find(|f| f.kind == kind) {
+      Some(force) => {
+        force.value = value;
+        force.remaining = seconds;
       },
       None => {
-        self.forces.push((name.to_owned(), value));
+        self.forces.push(Force { kind, value, remaining: seconds });
       }
     }
   }
 
-  pub fn get_force(&mut self, name: &str) -> Vec2 {
-    for (n, force) in &self.forces {
-      if n == name {
-        return force.clone();
-      }
-    }
-    let v = Vec2::new(0.,0.);
-    self.forces.push((name.to_owned(), v));
-    return v;
+  pub fn remove_force(&mut self, kind: ForceKind) {
+    self.forces.retain(|f| f.kind != kind);
+  }
+
+  /// One-shot change in velocity, applied on the next physics step
+  pub fn add_impulse(&mut self, impulse: Vec2) {
+    self.impulse += impulse;
   }
 
-  pub fn clear_x(&mut self) {
-    let mut forces = vec![];
-    for (n, force) in &self.forces {
-      forces.push((n.to_owned(), Vec2::new(0., force.y)));
+  pub fn take_impulse(&mut self) -> Vec2 {
+    let impulse = std::mem::replace(&mut self.impulse, Vec2::ZERO);
+    self.resisted(impulse)
+  }
+
+  /// Counts down timed forces, dropping the ones that have run out
+  pub fn tick(&mut self, seconds: f32) {
+    for force in self.forces.iter_mut() {
+      force.remaining -= seconds;
     }
-    self.forces = forces;
+    self.forces.retain(|f| f.remaining > 0.);
+  }
+
+  /// Records a surface the body is pressed against until the next collision pass.
+  /// A wall then stops the sideways part of a push but leaves the rest of it acting.
+  pub fn add_contact(&mut self, normal: Vec2) {
+    self.contacts.push(normal);
+  }
+
+  pub fn clear_contacts(&mut self) {
+    self.contacts.clear();
   }
 
-  pub fn clear_y(&mut self) {
-    let mut forces = vec![];
-    for (n, force) in &self.forces {
-      forces.push((n.to_owned(), Vec2::new(force.x, 0.)));
+  fn resisted(&self, mut v: Vec2) -> Vec2 {
+    for normal in &self.contacts {
+      let into = v.dot(*normal);
+      if into < 0. {
+        v -= *normal * into;
+      }
     }
-    self.forces = forces;
+    v
+  }
+}
+
+/// Physics positions from the last two ticks.
+/// Outside the physics stage the entity's transform is blended between them, so motion stays
+/// smooth at display rates that aren't a multiple of the tick rate.
+#[derive(Component)]
+pub struct Interpolated {
+  previous: Vec2,
+  current: Vec2
+}
+
+impl Interpolated {
+  pub fn new(position: Vec2) -> Self {
+    Interpolated { previous: position, current: position }
+  }
+
+  /// Where physics has the entity, as opposed to where it's drawn
+  pub fn position(&self) -> Vec2 {
+    self.current
+  }
+
+  /// Moves the entity from outside the physics stage without blending across the jump
+  pub fn teleport(&mut self, transform: &mut Transform, position: Vec2) {
+    self.previous = position;
+    self.current = position;
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
   }
 }
 
@@ -94,17 +259,23 @@ pub struct PhysicsMovementPlugin;
 impl Plugin for PhysicsMovementPlugin {
   fn build(&self, app:&mut App) {
       app
-        .add_system(physics_velocity.label("velocity").after("input"))
-        .add_system(movement.label("movement").after("velocity"))
-        .add_startup_system(new_physical_universe);
+        .add_stage_after(
+          CoreStage::Update,
+          PHYSICS_STAGE,
+          SystemStage::parallel()
+            .with_run_criteria(physics_ticks))
+        .add_system_to_stage(CoreStage::PreUpdate, advance_physics_clock)
+        .add_system_to_stage(PHYSICS_STAGE, restore_physics_position.label("restore").before("platforms").before("velocity"))
+        .add_system_to_stage(PHYSICS_STAGE, physics_velocity.label("velocity"))
+        .add_system_to_stage(PHYSICS_STAGE, movement.label("movement").after("velocity"))
+        .add_system_to_stage(PHYSICS_STAGE, record_physics_position.label("record").after("movement"))
+        .add_system_to_stage(CoreStage::PostUpdate, interpolate.label("interpolate").before(TransformSystem::TransformPropagate))
+        .init_resource::<PhysicalUniverse>()
+        .init_resource::<PhysicsClock>();
 
   }
 }
 
-fn new_physical_universe(mut commands: Commands) {
-  commands.insert_resource(PhysicalUniverse::new());
-}
-
 fn constrain(s:f32, m: f32) -> f32 {
   if s > m {
     return m;
@@ -112,42 +283,233 @@ fn constrain(s:f32, m: f32) -> f32 {
   if s < -m {
     return -m;
   }
-  return s;
+  s
 }
 
-fn physics_velocity(
-  mut query: Query<(&mut Velocity, &mut Accelleration)>,
-  universe: Res<PhysicalUniverse>,
-  time: Res<Time>
+/// Advances a body's velocity by one physics step: gravity and forces, then friction, then the speed limit
+fn step_velocity(
+  velocity: &mut Vec2,
+  accell: &mut Accelleration,
+  body: &PhysicsBody,
+  grounded: Option<&Grounded>,
+  zone: Option<&InZone>,
+  universe: &PhysicalUniverse
 ) {
-  let seconds = time.delta_seconds();
-  for (mut velocity, mut accell) in query.iter_mut() {
-    
-    let mut gravity = accell.get_force("GRAVITY");
-    gravity.y -= universe.gravity * seconds;
-    accell.set_force("GRAVITY", gravity);
+  let seconds = PHYSICS_DT;
+  let (zone_gravity, zone_drag) = zone.map_or((1., 0.), |z| (z.gravity_scale, z.drag));
 
-    velocity.value += accell.value() * seconds;
+  //semi-implicit euler, velocity is updated first and movement then uses the new velocity.
+  //gravity is a constant acceleration and isn't divided by mass like the other forces
+  let gravity = Vec2::new(0., -universe.gravity * body.gravity_scale * zone_gravity);
+  let acceleration = gravity + accell.value() / body.mass;
+  *velocity += acceleration * seconds + accell.take_impulse() / body.mass;
+  accell.tick(seconds);
 
-    let friction =  velocity.value * universe.friction * seconds;
-    velocity.value -= friction;
+  //friction drags velocity towards the ground's own velocity, which only conveyors have
+  let (friction_coefficient, surface_velocity) = match grounded {
+    Some(g) if g.on_ground => {
+      let mut coefficient = body.ground_friction;
+      let mut surface_velocity = Vec2::ZERO;
+      if let Some(surface) = g.surface {
+        coefficient.x = surface.friction.unwrap_or(coefficient.x);
+        surface_velocity.x = surface.conveyor;
+      }
+      //leaving the ground already, so ground friction mustn't eat into the jump
+      if velocity.y > 0. {
+        coefficient.y = body.air_friction.y;
+      }
+      (coefficient, surface_velocity)
+    },
+    _ => (body.air_friction, Vec2::ZERO)
+  };
+  //exact exponential decay, so strong drag settles on the surface velocity instead of overshooting it
+  let coefficient = friction_coefficient + Vec2::splat(zone_drag);
+  let decay = Vec2::new((-coefficient.x * seconds).exp(), (-coefficient.y * seconds).exp());
+  *velocity = surface_velocity + (*velocity - surface_velocity) * decay;
 
-    if velocity.value.length_squared() < universe.velocity_epsilon {
-      velocity.value.x = 0.;
-      velocity.value.y = 0.;
-    }
+  if velocity.length_squared() < universe.velocity_epsilon {
+    *velocity = Vec2::ZERO;
+  }
 
-    velocity.value.x = constrain(velocity.value.x, universe.speed_of_light);
-    velocity.value.y = constrain(velocity.value.y, universe.speed_of_light);
+  //max_speed.y doubles as terminal velocity when falling
+  velocity.x = constrain(velocity.x, body.max_speed.x);
+  velocity.y = constrain(velocity.y, body.max_speed.y);
+}
+
+/// Moves a body by one physics step at its current velocity
+fn step_position(position: &mut Vec3, velocity: Vec2) {
+  *position += (velocity * PHYSICS_DT).extend(0.);
+}
+
+#[allow(clippy::type_complexity)]
+fn physics_velocity(
+  mut query: Query<(&mut Velocity, &mut Accelleration, Option<&PhysicsBody>, Option<&Grounded>, Option<&InZone>)>,
+  universe: Res<PhysicalUniverse>
+) {
+  for (mut velocity, mut accell, body, grounded, zone) in query.iter_mut() {
+    let body = body.copied().unwrap_or_else(|| universe.default_body());
+    step_velocity(&mut velocity.value, &mut accell, &body, grounded, zone, &universe);
   }
 }
 
 fn movement(
-  mut query: Query<(&mut Transform, &Velocity)>,
-  time: Res<Time>
+  mut query: Query<(&mut Transform, &Velocity)>
 ) {
   for (mut position, velocity) in query.iter_mut() {
-    let shift = velocity.value * time.delta_seconds();
-    position.translation += shift.extend(0.);
+    step_position(&mut position.translation, velocity.value);
+  }
+}
+
+
+/// Undoes last frame's blending so the tick starts from the true physics position
+fn restore_physics_position(
+  mut query: Query<(&mut Transform, &mut Interpolated)>
+) {
+  for (mut transform, mut interpolated) in query.iter_mut() {
+    interpolated.previous = interpolated.current;
+    transform.translation.x = interpolated.current.x;
+    transform.translation.y = interpolated.current.y;
+  }
+}
+
+fn record_physics_position(
+  mut query: Query<(&Transform, &mut Interpolated)>
+) {
+  for (transform, mut interpolated) in query.iter_mut() {
+    interpolated.current = transform.translation.truncate();
+  }
+}
+
+fn advance_physics_clock(time: Res<Time>, mut clock: ResMut<PhysicsClock>) {
+  clock.advance(time.delta_seconds_f64());
+}
+
+/// Runs the physics stage again for as long as a whole step is banked
+fn physics_ticks(mut clock: ResMut<PhysicsClock>) -> ShouldRun {
+  if clock.banked >= PHYSICS_STEP {
+    clock.banked -= PHYSICS_STEP;
+    ShouldRun::YesAndCheckAgain
+  } else {
+    ShouldRun::No
+  }
+}
+
+fn interpolate(
+  mut query: Query<(&mut Transform, &Interpolated)>,
+  clock: Res<PhysicsClock>
+) {
+  let alpha = clock.overstep() as f32;
+  for (mut transform, interpolated) in query.iter_mut() {
+    let blended = interpolated.previous.lerp(interpolated.current, alpha);
+    transform.translation.x = blended.x;
+    transform.translation.y = blended.y;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::player::Player;
+
+  /// Runs the physics plugin in an app fed frames of the given length,
+  /// and returns where the body is drawn every sixth of a second
+  fn simulate(frame_time: f64, duration: f64) -> Vec<Vec2> {
+    let mut app = App::new();
+    app.insert_resource(Time::default())
+      .add_plugin(PhysicsMovementPlugin);
+
+    let mut accel = Accelleration::new();
+    accel.add_impulse(Vec2::new(150., 300.));
+    accel.set_timed_force(ForceKind::Wind, Vec2::new(-200., 0.), 0.25);
+    let body = app.world.spawn()
+      .insert_bundle((Transform::default(), Velocity::new(), accel, Interpolated::new(Vec2::ZERO)))
+      .id();
+
+    let frames_per_sample = (1. / (6. * frame_time)).round() as usize;
+    let frames = (duration / frame_time).round() as usize;
+    let mut samples = Vec::new();
+    for frame in 1..=frames {
+      app.world.get_resource_mut::<PhysicsClock>().unwrap().advance(frame_time);
+      app.update();
+      if frame % frames_per_sample == 0 {
+        samples.push(app.world.get::<Transform>(body).unwrap().translation.truncate());
+      }
+    }
+    samples
+  }
+
+  #[test]
+  fn trajectory_is_the_same_at_any_frame_rate() {
+    let reference = simulate(1. / 60., 2.);
+    assert_eq!(reference.len(), 12);
+    assert!(reference[0].y > 0., "the body never moved");
+    for frame_time in [1. / 30., 1. / 144.] {
+      let trajectory = simulate(frame_time, 2.);
+      assert_eq!(trajectory.len(), reference.len());
+      for (drawn, expected) in trajectory.iter().zip(&reference) {
+        //rounding can leave the last step banked, interpolation then draws nearly the same spot
+        assert!(drawn.distance(*expected) < 0.01, "drew {} at {}Hz, expected {}", drawn, 1. / frame_time, expected);
+      }
+    }
+  }
+
+  /// Steps a body that starts at rest on the ground at the origin, keeping `force` on it the whole time.
+  /// Collisions still report it grounded on the first step, as they do in game.
+  fn trajectory(impulse: Vec2, force: Vec2, seconds: f64) -> Vec<Vec2> {
+    let universe = PhysicalUniverse::new();
+    let body = universe.default_body();
+    let mut accel = Accelleration::new();
+    accel.add_impulse(impulse);
+    accel.set_force(ForceKind::PlayerAccel, force);
+    let mut velocity = Vec2::ZERO;
+    let mut position = Vec3::ZERO;
+    let ground = Grounded { on_ground: true, ..Default::default() };
+    (0..(seconds * PHYSICS_RATE) as usize).map(|step| {
+      let grounded = if step == 0 { Some(&ground) } else { None };
+      step_velocity(&mut velocity, &mut accel, &body, grounded, None, &universe);
+      step_position(&mut position, velocity);
+      position.truncate()
+    }).collect()
+  }
+
+  fn apex(points: &[Vec2]) -> f32 {
+    points.iter().map(|p| p.y).fold(f32::MIN, f32::max)
+  }
+
+  #[test]
+  fn falls_at_constant_gravity_up_to_terminal_velocity() {
+    let universe = PhysicalUniverse::new();
+    let drop = 5. * crate::TILE_SIZE;
+    let steps = trajectory(Vec2::ZERO, Vec2::ZERO, 2.).iter().position(|p| p.y <= -drop).unwrap() + 1;
+    let fall_time = steps as f32 * PHYSICS_DT;
+
+    //accelerating to the speed of light covers v²/2g, the rest of the drop is at that speed
+    let terminal = universe.speed_of_light;
+    let speeding_up = terminal * terminal / (2. * universe.gravity);
+    let expected = terminal / universe.gravity + (drop - speeding_up) / terminal;
+    assert!((fall_time - expected).abs() < expected * 0.03, "fell {} pixels in {}s, expected {}s", drop, fall_time, expected);
+  }
+
+  #[test]
+  fn jump_apex_matches_the_jump_speed() {
+    let universe = PhysicalUniverse::new();
+    let player = Player::new(0);
+    let points = trajectory(player.jump_impulse(), Vec2::ZERO, 2.);
+
+    let jump_speed = player.jump_impulse().y;
+    let expected = jump_speed * jump_speed / (2. * universe.gravity);
+    let height = apex(&points);
+    //air drag and the first step's gravity shave a little off the ideal v²/2g
+    assert!(height <= expected && height > expected * 0.85, "jumped {} pixels, expected {}", height, expected);
+    assert!(points.last().unwrap().y < 0.);
+  }
+
+  #[test]
+  fn holding_up_on_land_does_not_fly() {
+    let player = Player::new(0);
+    let jump = trajectory(player.jump_impulse(), Vec2::ZERO, 2.);
+    let held = trajectory(player.jump_impulse(), player.move_force(0., 1., false), 2.);
+    assert_eq!(apex(&held), apex(&jump));
+    assert!(held.last().unwrap().y < 0.);
   }
 }
@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+
+use crate::{
+  collisions::{CollisionGroups, Grounded},
+  geometry::AxisAlignedBoundingBox,
+  movement::{PHYSICS_STAGE, PHYSICS_DT}};
+
+pub struct PlatformPlugin;
+
+impl Plugin for PlatformPlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .add_system_to_stage(PHYSICS_STAGE, move_platforms.label("platforms").before("velocity"))
+        .add_system_to_stage(PHYSICS_STAGE, carry_riders.label("riders").after("platforms").before("collisions"));
+  }
+}
+
+/// Kinematic platform that follows a path loaded from a Tiled polyline or polygon.
+/// Polylines run back and forth, polygons loop around to the start.
+#[derive(Component)]
+pub struct MovingPlatform {
+  pub size: Vec2,
+  pub speed: f32,
+  pub groups: CollisionGroups,
+  path: Vec<Vec2>,
+  looped: bool,
+  target: usize,
+  reversing: bool,
+  delta: Vec2
+}
+
+impl MovingPlatform {
+  pub fn new(path: Vec<Vec2>, looped: bool, size: Vec2, speed: f32, groups: CollisionGroups) -> Self {
+    MovingPlatform { size, speed, groups, path, looped, target: 1, reversing: false, delta: Vec2::ZERO }
+  }
+
+  pub fn bounds(&self, transform: &Transform) -> AxisAlignedBoundingBox {
+    AxisAlignedBoundingBox::new(transform.translation.truncate(), self.size)
+  }
+
+  /// How far the platform moved on its last step
+  pub fn delta(&self) -> Vec2 {
+    self.delta
+  }
+
+  fn next_target(&mut self) {
+    let last = self.path.len() - 1;
+    if self.looped {
+      self.target = (self.target + 1) % self.path.len();
+    } else if self.reversing {
+      if self.target == 0 {
+        self.reversing = false;
+        self.target = 1;
+      } else {
+        self.target -= 1;
+      }
+    } else if self.target == last {
+      self.reversing = true;
+      self.target = last - 1;
+    } else {
+      self.target += 1;
+    }
+  }
+}
+
+fn move_platforms(
+  mut platforms: Query<(&mut Transform, &mut MovingPlatform)>
+) {
+  for (mut transform, mut platform) in platforms.iter_mut() {
+    let start = transform.translation.truncate();
+    if platform.path.len() < 2 {
+      platform.delta = Vec2::ZERO;
+      continue;
+    }
+    let mut pos = start;
+    let mut travel = platform.speed * PHYSICS_DT;
+    let mut corners = 0;
+    //a fast platform can pass more than one corner in a frame
+    while travel > 0. && corners <= platform.path.len() {
+      let target = platform.path[platform.target];
+      let remaining = target - pos;
+      let distance = remaining.length();
+      if distance > travel {
+        pos += remaining / distance * travel;
+        break;
+      }
+      pos = target;
+      travel -= distance;
+      corners += 1;
+      platform.next_target();
+    }
+    platform.delta = pos - start;
+    transform.translation = pos.extend(transform.translation.z);
+  }
+}
+
+fn carry_riders(
+  mut riders: Query<(&mut Transform, &Grounded)>,
+  platforms: Query<&MovingPlatform>
+) {
+  for (mut transform, grounded) in riders.iter_mut() {
+    if let Some(platform) = grounded.platform.and_then(|p| platforms.get(p).ok()) {
+      transform.translation += platform.delta().extend(0.);
+    }
+  }
+}
@@ -2,166 +2,156 @@ use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 
 use crate::{
-    spritesheet::{CharacterAtlas, spawn_sprite, CHARACTER_TILE_SIZE}, 
+    spritesheet::{SpriteSheets, spawn_sprite}, 
     TILE_SIZE, 
-    movement::{Accelleration, Velocity}, collisions::Collidable};
+    movement::{Accelleration, Velocity, Interpolated, ForceKind},
+    zones::InZone, collisions::{Collidable, CollisionGroups, Grounded},
+    input::{Action, ActionState, InputBindings, InputSource},
+    tilemap::MapInfo,
+    animation::Animator,
+    health::Health,
+    respawn::Respawning};
 
 pub struct PlayerPlugin;
 
 #[derive(Default, Component, Inspectable)]
 pub struct Player {
+    /// join order, picks the spawn point
+    pub index: usize,
     speed: f32,
-    up: bool,
-    down: bool,
-    left: bool,
-    right: bool,
-    stick_pos: Vec2
+    /// upward impulse given when jumping off the ground
+    jump_speed: f32
 }
 
-impl Plugin for PlayerPlugin {
-    fn build(&self, app:&mut App) {
-        app
-            .add_startup_system(spawn_player)
-            .add_system(gamepad_connections.label("gamepads"))
-            .add_system(keyboard_input.label("input"))
-            .add_system(gamepad_input.label("input").after("gamepads"))
-            .add_system(player_movement.label("player-movement").after("input").before("movement"))
-            .add_system(camera_follow.after("movement"));
+impl Player {
+    pub fn new(index: usize) -> Self {
+        Player{index, speed: 100.0, jump_speed: 200.0}
     }
-}
 
-fn camera_follow(player_query: Query<&Transform, With<Player>>,
-    mut camera_query: Query<&mut Transform, (Without<Player>, With<Camera>)>) {
-        let player_transform = player_query.single();
-        let mut camera_transform = camera_query.single_mut();
-        camera_transform.translation.x = player_transform.translation.x;
-        camera_transform.translation.y = player_transform.translation.y;
+    /// Push from the movement keys, each axis from -1 to 1.
+    /// Up only pushes while swimming, on land jumping is the way up.
+    pub fn move_force(&self, horizontal: f32, vertical: f32, swimming: bool) -> Vec2 {
+        let speed = if swimming { self.speed * SWIM_FACTOR } else { self.speed };
+        let vertical = if swimming { vertical } else { vertical.min(0.) };
+        Vec2::new(horizontal, vertical) * speed * TILE_SIZE
     }
 
-fn keyboard_input(
-    mut player_query: Query<&mut Player>,
-    keyboard: Res<Input<KeyCode>>
-){
-    let mut player = player_query.single_mut();
-    player.up = keyboard.pressed(KeyCode::W);
-    player.down = keyboard.pressed(KeyCode::S);
-    player.left = keyboard.pressed(KeyCode::A);
-    player.right = keyboard.pressed(KeyCode::D);
-}
-
-fn gamepad_input(
-    mut player_query: Query<&mut Player>,
-    axes: Res<Axis<GamepadAxis>>,
-    // buttons: Res<Input<GamepadButton>>,
-    my_gamepad: Option<Res<MyGamepad>>
-) {
-    let gamepad = if let Some(gp) = my_gamepad {
-        // a gamepad is connected, we have the id
-        gp.0
-    } else {
-        // no gamepad is connected
-        return;
-    };
-
-    // The joysticks are represented using a separate axis for X and Y
-
-    let axis_lx = GamepadAxis(gamepad, GamepadAxisType::LeftStickX);
-    let axis_ly = GamepadAxis(gamepad, GamepadAxisType::LeftStickY);
-
-    if let (Some(x), Some(y)) = (axes.get(axis_lx), axes.get(axis_ly)) {
-        // combine X and Y into one vector
-        let mut player = player_query.single_mut();
-        player.stick_pos.x = x;
-        player.stick_pos.y = y;
-        player.up = y > 0.5;
-        player.down = y < -0.5;
-        player.left = x < -0.5;
-        player.right = x > 0.5;
+    pub fn jump_impulse(&self) -> Vec2 {
+        Vec2::new(0., self.jump_speed)
     }
+}
 
-    // // In a real game, the buttons would be configurable, but here we hardcode them
-    // let jump_button = GamepadButton(gamepad, GamepadButtonType::South);
-    // let heal_button = GamepadButton(gamepad, GamepadButtonType::East);
-
-    // if buttons.just_pressed(jump_button) {
-    //     // button just pressed: make the player jump
-    // }
+/// Swimming is slower than moving on land
+const SWIM_FACTOR: f32 = 0.5;
+/// Upward kick given each time up or jump is pressed in water
+const SWIM_STROKE: f32 = 120.;
 
-    // if buttons.pressed(heal_button) {
-    //     // button being held down: heal the player
-    // }
+impl Plugin for PlayerPlugin {
+    fn build(&self, app:&mut App) {
+        app
+            //spawn points are read from the map, which is loaded during startup
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_first_player)
+            .add_system(join_players.before("input"))
+            .add_system(player_movement.label("player-movement").after("input"));
+    }
 }
 
+#[allow(clippy::type_complexity)]
 fn player_movement(
-    mut player_query: Query<(&Player, &mut Accelleration)>
+    mut player_query: Query<(&Player, &ActionState, &mut Accelleration, Option<&Grounded>, Option<&InZone>), Without<Respawning>>
 ) {
-    let (player, mut accel) = player_query.single_mut();
-    let mut player_accel = accel.get_force("PLAYER_ACCEL");
-    if player.up {
-        player_accel.y = player.speed * TILE_SIZE;
-    } else if player.down {
-        player_accel.y = -player.speed * TILE_SIZE;
-    } else {
-        player_accel.y = 0.;
-    }
+    for (player, actions, mut accel, grounded, zone) in player_query.iter_mut() {
+        let swimming = zone.map_or(false, |z| z.swimming);
+        accel.set_force(ForceKind::PlayerAccel, player.move_force(actions.horizontal(), actions.vertical(), swimming));
 
-    if player.left {
-        player_accel.x = -player.speed * TILE_SIZE;
-    } else if player.right {
-        player_accel.x = player.speed * TILE_SIZE;
-    } else {
-        player_accel.x = 0.;
+        if swimming {
+            if actions.just_pressed(Action::MoveUp) || actions.just_pressed(Action::Jump) {
+                accel.add_impulse(Vec2::new(0., SWIM_STROKE));
+            }
+        } else if actions.just_pressed(Action::Jump) && grounded.map_or(false, |g| g.on_ground) {
+            accel.add_impulse(player.jump_impulse());
+        }
     }
-    accel.set_force("PLAYER_ACCEL", player_accel);
 }
 
-/// Simple resource to store the ID of the connected gamepad.
-/// We need to know which gamepad to use for player input.
-struct MyGamepad(Gamepad);
+fn spawn_first_player(mut commands: Commands, sheets: Res<SpriteSheets>, map: Res<MapInfo>, assets: Res<AssetServer>) {
+    spawn_player(&mut commands, &sheets, &map, &assets, 0, InputSource::Keyboard(0));
+}
 
-fn gamepad_connections(
+/// Extra keyboard players join by pressing jump, gamepad players join when their gamepad connects
+#[allow(clippy::too_many_arguments)]
+fn join_players(
     mut commands: Commands,
-    my_gamepad: Option<Res<MyGamepad>>,
+    sheets: Res<SpriteSheets>,
+    map: Res<MapInfo>,
+    assets: Res<AssetServer>,
+    bindings: Res<InputBindings>,
+    keyboard: Res<Input<KeyCode>>,
     mut gamepad_evr: EventReader<GamepadEvent>,
+    players: Query<&InputSource, With<Player>>
 ) {
+    let mut sources: Vec<InputSource> = players.iter().copied().collect();
+    let mut joining = Vec::new();
+
+    for scheme in 0..bindings.keyboards.len() {
+        let source = InputSource::Keyboard(scheme);
+        let jumped = bindings.keys(scheme).iter()
+            .any(|(key, action)| *action == Action::Jump && keyboard.just_pressed(*key));
+        if jumped && !sources.contains(&source) {
+            joining.push(source);
+        }
+    }
+
     for GamepadEvent(id, kind) in gamepad_evr.iter() {
         match kind {
             GamepadEventType::Connected => {
                 println!("New gamepad connected with ID: {:?}", id);
-
-                // if we don't have any gamepad yet, use this one
-                if my_gamepad.is_none() {
-                    commands.insert_resource(MyGamepad(*id));
-                }
+                joining.push(InputSource::Gamepad(*id));
             }
             GamepadEventType::Disconnected => {
+                //the player stays in the level and picks up again if the gamepad reconnects
                 println!("Lost gamepad connection with ID: {:?}", id);
-
-                // if it's the one we previously associated with the player,
-                // disassociate it:
-                if let Some(MyGamepad(old_id)) = my_gamepad.as_deref() {
-                    if old_id == id {
-                        commands.remove_resource::<MyGamepad>();
-                    }
-                }
             }
             // other events are irrelevant
             _ => {}
         }
     }
+
+    for source in joining {
+        if sources.contains(&source) {
+            continue;
+        }
+        spawn_player(&mut commands, &sheets, &map, &assets, sources.len(), source);
+        sources.push(source);
+    }
 }
 
-fn spawn_player(mut commands: Commands, atlas: Res<CharacterAtlas>) {
+fn spawn_player(
+    commands: &mut Commands,
+    sheets: &SpriteSheets,
+    map: &MapInfo,
+    assets: &AssetServer,
+    index: usize,
+    source: InputSource
+) {
+    let position = map.spawn_point(index).extend(900.0 + index as f32);
+    let sheet = sheets.expect("characters");
     let player = spawn_sprite(
-        &mut commands, 
-        &atlas as &CharacterAtlas, 
-        1, 
-        Vec3::new(32.0,-320.0,900.0));
+        commands, 
+        sheet, 
+        0, 
+        position);
     
     commands.entity(player)
-        .insert(Name::new("Player"))
+        .insert(Name::new(format!("Player {}", index + 1)))
         .insert(Velocity::new())
         .insert(Accelleration::new())
-        .insert(Collidable{size: CHARACTER_TILE_SIZE})
-        .insert(Player{speed: 100.0, ..Default::default()}).id();
-}
\ No newline at end of file
+        .insert(Grounded::default())
+        .insert(Interpolated::new(position.truncate()))
+        .insert(Collidable::new(sheet.tile_size.x).with_groups(CollisionGroups::PLAYER, CollisionGroups::ALL))
+        .insert(Health::new(3.0))
+        .insert(ActionState::default())
+        .insert(Animator::new(assets.load("player.anim.ron")))
+        .insert(source)
+        .insert(Player::new(index));
+}
@@ -0,0 +1,194 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+
+use crate::{
+  collisions::Collidable,
+  geometry::Line,
+  tilemap::TileGrid};
+
+/// A single contact found by a [`WorldRaycast`] query.
+/// `entity` is either the collidable that was struck or the tile layer that owns the tile.
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+  //only read by line of sight checks so far
+  #[allow(dead_code)]
+  pub entity: Entity,
+  pub point: Vec2,
+  #[allow(dead_code)]
+  pub normal: Vec2,
+  pub t: f32,
+  #[allow(dead_code)]
+  pub tile: Option<IVec2>
+}
+
+/// System parameter for casting rays and boxes through the world.
+/// Checks every solid tile layer and every `Collidable` entity whose collision layer is in `mask`.
+#[derive(SystemParam)]
+pub struct WorldRaycast<'w, 's> {
+  tile_layers: Query<'w, 's, (Entity, &'static TileGrid)>,
+  collidables: Query<'w, 's, (Entity, &'static Transform, &'static Collidable)>
+}
+
+impl<'w, 's> WorldRaycast<'w, 's> {
+
+  pub fn cast_ray(&self, origin: Vec2, vector: Vec2, mask: u32, exclude: Option<Entity>) -> Vec<RayHit> {
+    self.cast_box(origin, Vec2::ZERO, vector, mask, exclude)
+  }
+
+  /// Sweeps a box centered on `origin` along `vector` and returns every hit ordered by distance.
+  /// Tile layers report only their nearest tile, since it occludes the tiles behind it.
+  pub fn cast_box(&self, origin: Vec2, size: Vec2, vector: Vec2, mask: u32, exclude: Option<Entity>) -> Vec<RayHit> {
+    let line = Line::new(origin, vector);
+    let mut hits = Vec::new();
+
+    for (entity, grid) in self.tile_layers.iter() {
+      if grid.groups().map_or(true, |groups| groups.layer & mask == 0) {
+        continue;
+      }
+      if let Some(hit) = grid.cast_box(line, size) {
+        hits.push(RayHit{entity, point: hit.pos(), normal: hit.normal(), t: hit.t(), tile: hit.tile()});
+      }
+    }
+
+    for (entity, transform, collidable) in self.collidables.iter() {
+      if Some(entity) == exclude || collidable.groups.layer & mask == 0 {
+        continue;
+      }
+      let shape = collidable.shape_at(transform.translation.truncate());
+      if let Some(hit) = shape.intersection(line, size) {
+        hits.push(RayHit{entity, point: hit.pos(), normal: hit.normal(), t: hit.t(), tile: None});
+      }
+    }
+
+    hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+    hits
+  }
+
+  /// The first thing a ray strikes, ignoring `exclude`
+  pub fn first_hit(&self, origin: Vec2, vector: Vec2, mask: u32, exclude: Option<Entity>) -> Option<RayHit> {
+    self.cast_ray(origin, vector, mask, exclude).into_iter().next()
+  }
+
+  /// True if nothing solid lies on the segment between the two points.
+  /// Entities in `ignore` (usually the looker and its target) don't block the view.
+  /// Waiting on enemies, which will use it to spot players.
+  #[allow(dead_code)]
+  pub fn line_of_sight(&self, from: Vec2, to: Vec2, mask: u32, ignore: &[Entity]) -> bool {
+    self.cast_ray(from, to - from, mask, None).iter()
+      .all(|hit| ignore.contains(&hit.entity))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bevy::{ecs::system::SystemState, utils::{HashMap, HashSet}};
+
+  use super::*;
+  use crate::collisions::{CollisionGroups, CollisionShape};
+
+  /// A solid tile layer with tiles at the given grid coordinates
+  fn spawn_tiles(world: &mut World, tiles: &[IVec2]) -> Entity {
+    let tiles: HashSet<IVec2> = tiles.iter().copied().collect();
+    world.spawn()
+      .insert(TileGrid::new(tiles, HashMap::default(), HashMap::default(), Some(CollisionGroups::default())))
+      .id()
+  }
+
+  fn spawn_collidable(world: &mut World, position: Vec2, collidable: Collidable) -> Entity {
+    world.spawn()
+      .insert(Transform::from_translation(position.extend(0.)))
+      .insert(collidable)
+      .id()
+  }
+
+  fn cast(world: &mut World, origin: Vec2, vector: Vec2, mask: u32, exclude: Option<Entity>) -> Vec<RayHit> {
+    let mut state: SystemState<WorldRaycast> = SystemState::new(world);
+    let raycast = state.get_mut(world);
+    raycast.cast_ray(origin, vector, mask, exclude)
+  }
+
+  #[test]
+  fn tile_layers_report_their_nearest_tile() {
+    let mut world = World::new();
+    let layer = spawn_tiles(&mut world, &[IVec2::new(3, 0), IVec2::new(5, 0)]);
+
+    let hits = cast(&mut world, Vec2::ZERO, Vec2::new(200., 0.), CollisionGroups::ALL, None);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].entity, layer);
+    assert_eq!(hits[0].tile, Some(IVec2::new(3, 0)));
+    assert_eq!(hits[0].point, Vec2::new(45., 0.));
+    assert_eq!(hits[0].normal, Vec2::new(-1., 0.));
+  }
+
+  #[test]
+  fn hits_are_ordered_by_distance() {
+    let mut world = World::new();
+    let layer = spawn_tiles(&mut world, &[IVec2::new(3, 0)]);
+    let looker = spawn_collidable(&mut world, Vec2::ZERO, Collidable::new(10.));
+    let blocker = spawn_collidable(&mut world, Vec2::new(30., 0.), Collidable::new(10.));
+
+    let hits = cast(&mut world, Vec2::ZERO, Vec2::new(200., 0.), CollisionGroups::ALL, Some(looker));
+    let entities: Vec<Entity> = hits.iter().map(|hit| hit.entity).collect();
+    assert_eq!(entities, vec![blocker, layer]);
+    assert_eq!(hits[0].point, Vec2::new(25., 0.));
+  }
+
+  #[test]
+  fn first_hit_respects_the_mask() {
+    let mut world = World::new();
+    let layer = spawn_tiles(&mut world, &[IVec2::new(3, 0)]);
+    spawn_collidable(&mut world, Vec2::new(30., 0.),
+      Collidable::new(10.).with_groups(CollisionGroups::PLAYER, CollisionGroups::ALL));
+
+    let mut state: SystemState<WorldRaycast> = SystemState::new(&mut world);
+    let raycast = state.get_mut(&mut world);
+    let hit = raycast.first_hit(Vec2::ZERO, Vec2::new(200., 0.), CollisionGroups::WORLD, None);
+    assert_eq!(hit.map(|hit| hit.entity), Some(layer));
+  }
+
+  #[test]
+  fn line_of_sight_is_blocked_by_tiles_but_not_ignored_entities() {
+    let mut world = World::new();
+    spawn_tiles(&mut world, &[IVec2::new(3, -2)]);
+    let looker = spawn_collidable(&mut world, Vec2::ZERO, Collidable::new(10.));
+    let target = spawn_collidable(&mut world, Vec2::new(100., 0.), Collidable::new(10.));
+
+    let mut state: SystemState<WorldRaycast> = SystemState::new(&mut world);
+    let raycast = state.get_mut(&mut world);
+    assert!(raycast.line_of_sight(Vec2::ZERO, Vec2::new(100., 0.), CollisionGroups::ALL, &[looker, target]));
+    assert!(!raycast.line_of_sight(Vec2::ZERO, Vec2::new(100., 0.), CollisionGroups::ALL, &[looker]));
+    assert!(!raycast.line_of_sight(Vec2::ZERO, Vec2::new(54., 36.), CollisionGroups::ALL, &[looker]));
+  }
+
+  #[test]
+  fn circles_are_hit_on_their_curve() {
+    let mut world = World::new();
+    spawn_collidable(&mut world, Vec2::new(30., 0.), Collidable { shape: CollisionShape::Circle, ..Collidable::new(20.) });
+
+    let hit = cast(&mut world, Vec2::ZERO, Vec2::new(60., 0.), CollisionGroups::ALL, None)[0];
+    assert_eq!(hit.point, Vec2::new(20., 0.));
+    assert_eq!(hit.normal, Vec2::new(-1., 0.));
+
+    //passes through the corner of the circle's bounding box without touching the circle
+    assert!(cast(&mut world, Vec2::new(12., 0.), Vec2::new(20., 20.), CollisionGroups::ALL, None).is_empty());
+
+    let hit = cast(&mut world, Vec2::new(50., 20.), Vec2::new(-20., -20.), CollisionGroups::ALL, None)[0];
+    assert!((hit.normal - Vec2::ONE.normalize()).length() < 1e-4);
+  }
+
+  #[test]
+  fn boxes_sweep_over_the_round_end_of_capsules() {
+    let mut world = World::new();
+    spawn_collidable(&mut world, Vec2::ZERO, Collidable { shape: CollisionShape::Capsule, ..Collidable::new(30.) });
+    let mut state: SystemState<WorldRaycast> = SystemState::new(&mut world);
+    let raycast = state.get_mut(&mut world);
+
+    let hit = raycast.cast_box(Vec2::new(0., 50.), Vec2::splat(4.), Vec2::new(0., -100.), CollisionGroups::ALL, None)[0];
+    assert!((hit.point - Vec2::new(0., 17.)).length() < 1e-4);
+    assert!((hit.normal - Vec2::Y).length() < 1e-4);
+
+    //off to the side the box's corner meets the curve well below the top of the bounding box
+    let hit = raycast.cast_box(Vec2::new(11.5, 50.), Vec2::splat(4.), Vec2::new(0., -100.), CollisionGroups::ALL, None)[0];
+    assert!(hit.point.y < 11.);
+    assert!(hit.normal.x > 0.5);
+  }
+}
@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+
+use crate::{
+  TILE_SIZE,
+  player::Player,
+  movement::{Accelleration, Interpolated, Velocity},
+  health::{DeathEvent, Health},
+  collisions::{Collidable, CollisionGroups},
+  raycast::WorldRaycast,
+  tilemap::MapInfo,
+  triggers::{Trigger, TriggerEntered}};
+
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .init_resource::<ActiveCheckpoint>()
+        .add_event::<RespawnEvent>()
+        .add_startup_system_to_stage(StartupStage::PostStartup, spawn_fade_overlay)
+        .add_system(touch_checkpoints)
+        .add_system(respawn_dead_players.label("respawn-requests").after("damage"))
+        .add_system(start_respawn.label("start-respawn").after("respawn-requests"))
+        .add_system(run_respawn.after("start-respawn").before("player-movement"))
+        .add_system(fade_overlay.after("start-respawn"));
+  }
+}
+
+/// Seconds to fade out, and again to fade back in
+const FADE_TIME: f32 = 0.5;
+/// Draws the overlay just in front of everything else the camera sees
+const OVERLAY_DEPTH: f32 = -0.5;
+/// Big enough to cover the screen at any zoom
+const OVERLAY_SIZE: f32 = 10000.;
+/// How far below a checkpoint to look for a floor to stand on
+const FLOOR_PROBE: f32 = TILE_SIZE * 8.;
+
+/// The last checkpoint any player touched, `None` until one has been reached
+#[derive(Default)]
+pub struct ActiveCheckpoint {
+  pub position: Option<Vec2>
+}
+
+impl ActiveCheckpoint {
+  /// Where the nth player comes back, falling back to their spawn point
+  pub fn respawn_point(&self, map: &MapInfo, index: usize) -> Vec2 {
+    match self.position {
+      Some(position) => position + Vec2::new(index as f32 * TILE_SIZE, 0.),
+      None => map.spawn_point(index)
+    }
+  }
+}
+
+pub fn is_checkpoint_type(obj_type: &str) -> bool {
+  obj_type == "checkpoint"
+}
+
+/// Sends a player back to the active checkpoint
+pub struct RespawnEvent {
+  pub entity: Entity
+}
+
+/// A player partway through fading out and back in.
+/// They are moved and reset once the screen is fully dark.
+#[derive(Component)]
+pub struct Respawning {
+  elapsed: f32,
+  moved: bool,
+  target: Vec2
+}
+
+impl Respawning {
+  /// How dark the screen should be, from 0 to 1
+  fn fade(&self) -> f32 {
+    if self.elapsed < FADE_TIME {
+      self.elapsed / FADE_TIME
+    } else {
+      (1. - (self.elapsed - FADE_TIME) / FADE_TIME).max(0.)
+    }
+  }
+}
+
+#[derive(Component)]
+struct FadeOverlay;
+
+fn spawn_fade_overlay(mut commands: Commands, cameras: Query<Entity, With<Camera>>) {
+  for camera in cameras.iter() {
+    let overlay = commands
+      .spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+          color: Color::rgba(0., 0., 0., 0.),
+          custom_size: Some(Vec2::splat(OVERLAY_SIZE)),
+          ..Default::default()
+        },
+        transform: Transform::from_xyz(0., 0., OVERLAY_DEPTH),
+        ..Default::default()
+      })
+      .insert(Name::new("Fade"))
+      .insert(FadeOverlay)
+      .id();
+    commands.entity(camera).add_child(overlay);
+  }
+}
+
+fn touch_checkpoints(
+  mut entered: EventReader<TriggerEntered>,
+  triggers: Query<&Trigger>,
+  players: Query<(), With<Player>>,
+  mut active: ResMut<ActiveCheckpoint>
+) {
+  for event in entered.iter() {
+    if players.get(event.entity).is_err() {
+      continue;
+    }
+    if let Ok(trigger) = triggers.get(event.trigger) {
+      if is_checkpoint_type(&trigger.kind) {
+        active.position = Some(trigger.center());
+      }
+    }
+  }
+}
+
+fn respawn_dead_players(
+  mut deaths: EventReader<DeathEvent>,
+  players: Query<(), With<Player>>,
+  mut respawns: EventWriter<RespawnEvent>
+) {
+  for death in deaths.iter() {
+    if players.get(death.entity).is_ok() {
+      respawns.send(RespawnEvent { entity: death.entity });
+    }
+  }
+}
+
+/// Checkpoints are volumes that may float above the ground, so players come back standing
+/// on the first floor below the point, or at the point itself if there's none in reach
+fn landing_point(raycast: &WorldRaycast, entity: Entity, collidable: &Collidable, point: Vec2) -> Vec2 {
+  //other players don't count as floor
+  let mask = collidable.groups.mask & !CollisionGroups::PLAYER;
+  match raycast.first_hit(point, Vec2::new(0., -FLOOR_PROBE), mask, Some(entity)) {
+    Some(hit) => hit.point + Vec2::new(0., collidable.size / 2.),
+    None => point
+  }
+}
+
+fn start_respawn(
+  mut commands: Commands,
+  mut events: EventReader<RespawnEvent>,
+  map: Res<MapInfo>,
+  active: Res<ActiveCheckpoint>,
+  raycast: WorldRaycast,
+  players: Query<(&Player, &Collidable), Without<Respawning>>
+) {
+  for event in events.iter() {
+    if let Ok((player, collidable)) = players.get(event.entity) {
+      let point = active.respawn_point(&map, player.index);
+      let target = landing_point(&raycast, event.entity, collidable, point);
+      commands.entity(event.entity).insert(Respawning { elapsed: 0., moved: false, target });
+    }
+  }
+}
+
+#[allow(clippy::type_complexity)]
+fn run_respawn(
+  mut commands: Commands,
+  time: Res<Time>,
+  mut players: Query<(Entity, &mut Respawning, &mut Transform, &mut Interpolated, &mut Velocity, &mut Accelleration, Option<&mut Health>)>
+) {
+  for (entity, mut respawning, mut transform, mut interpolated, mut velocity, mut accel, health) in players.iter_mut() {
+    respawning.elapsed += time.delta_seconds();
+    //hold still while the screen goes dark
+    velocity.value = Vec2::ZERO;
+
+    if !respawning.moved && respawning.elapsed >= FADE_TIME {
+      *accel = Accelleration::new();
+      let target = respawning.target;
+      interpolated.teleport(&mut transform, target);
+      if let Some(mut health) = health {
+        health.revive();
+      }
+      respawning.moved = true;
+    }
+    if respawning.elapsed >= FADE_TIME * 2. {
+      commands.entity(entity).remove::<Respawning>();
+    }
+  }
+}
+
+fn fade_overlay(
+  respawning: Query<&Respawning>,
+  mut overlays: Query<&mut Sprite, With<FadeOverlay>>
+) {
+  let fade = respawning.iter().map(|r| r.fade()).fold(0., f32::max);
+  for mut sprite in overlays.iter_mut() {
+    sprite.color.set_a(fade);
+  }
+}
@@ -0,0 +1,80 @@
+use std::marker::PhantomData;
+
+use bevy::{
+  prelude::*,
+  asset::{Asset, AssetLoader, LoadContext, LoadedAsset},
+  utils::BoxedFuture};
+use serde::de::DeserializeOwned;
+
+/// Asset types read straight out of a RON file.
+/// The compound extension, like `universe.ron`, tells the asset server which loader to use.
+pub trait RonAsset: Asset + DeserializeOwned {
+  const EXTENSIONS: &'static [&'static str];
+}
+
+pub struct RonLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for RonLoader<T> {
+  fn default() -> Self {
+    RonLoader(PhantomData)
+  }
+}
+
+impl<T: RonAsset> AssetLoader for RonLoader<T> {
+  fn load<'a>(
+    &'a self,
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext
+  ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+    Box::pin(async move {
+      let asset = ron::de::from_bytes::<T>(bytes)?;
+      load_context.set_default_asset(LoadedAsset::new(asset));
+      Ok(())
+    })
+  }
+
+  fn extensions(&self) -> &[&str] {
+    T::EXTENSIONS
+  }
+}
+
+/// Keeps a RON file loaded so edits to it are picked up while the game runs
+pub struct HotReload<T: Asset> {
+  path: &'static str,
+  handle: Handle<T>
+}
+
+impl<T: RonAsset> HotReload<T> {
+  /// The file's contents if it has finished loading or been edited since the last call
+  pub fn reloaded<'a>(&self, events: &mut EventReader<AssetEvent<T>>, loaded: &'a Assets<T>) -> Option<&'a T> {
+    let mut latest = None;
+    for event in events.iter() {
+      match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } if *handle == self.handle => {
+          latest = loaded.get(handle);
+        },
+        _ => {}
+      }
+    }
+    latest
+  }
+}
+
+pub trait HotReloadApp {
+  /// Registers `T` as a RON asset and loads `path` at startup as a [`HotReload`] resource
+  fn add_hot_reload<T: RonAsset>(&mut self, path: &'static str) -> &mut Self;
+}
+
+impl HotReloadApp for App {
+  fn add_hot_reload<T: RonAsset>(&mut self, path: &'static str) -> &mut Self {
+    self
+      .add_asset::<T>()
+      .init_asset_loader::<RonLoader<T>>()
+      .insert_resource(HotReload::<T> { path, handle: Handle::default() })
+      .add_startup_system(load_hot_reload::<T>)
+  }
+}
+
+fn load_hot_reload<T: RonAsset>(mut file: ResMut<HotReload<T>>, assets: Res<AssetServer>) {
+  file.handle = assets.load(file.path);
+}
@@ -1,31 +1,53 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+use tiled::Loader;
 
-pub const CHARACTER_TILE_SIZE: f32 = 24.;
+/// Describes every sprite sheet, read once at startup before anything is spawned
+const MANIFEST: &str = "assets/sprites.ron";
 
 pub struct SpriteSheetPlugin;
 
-pub trait AtlasBox {
-    fn atlas(&self) -> Handle<TextureAtlas>;
+/// Where a sheet's image and grid come from
+#[derive(Debug, Deserialize)]
+enum SheetSource {
+    /// an image cut into a grid of equal tiles
+    Grid {
+        image: String,
+        tile_size: Vec2,
+        columns: usize,
+        rows: usize,
+        padding: Vec2
+    },
+    /// a Tiled tileset, which already knows its image and tile layout
+    Tileset(String)
 }
 
-pub struct BackgroundAtlas(Handle<TextureAtlas>);
-impl AtlasBox for BackgroundAtlas {
-    fn atlas(&self) -> Handle<TextureAtlas> {
-        self.0.clone()
-    }
+#[derive(Debug, Deserialize)]
+struct SpriteManifest {
+    sheets: std::collections::HashMap<String, SheetSource>
 }
 
-pub struct CharacterAtlas(Handle<TextureAtlas>);
-impl AtlasBox for CharacterAtlas {
-    fn atlas(&self) -> Handle<TextureAtlas> {
-        self.0.clone()
-    }
+/// A loaded sheet ready to spawn sprites from
+#[derive(Clone)]
+pub struct SpriteSheet {
+    pub atlas: Handle<TextureAtlas>,
+    pub tile_size: Vec2
 }
 
-pub struct TileAtlas(Handle<TextureAtlas>);
-impl AtlasBox for TileAtlas {
-    fn atlas(&self) -> Handle<TextureAtlas> {
-        self.0.clone()
+/// Every sheet from the manifest, keyed by the name it's given there
+#[derive(Default)]
+pub struct SpriteSheets {
+    sheets: HashMap<String, SpriteSheet>
+}
+
+impl SpriteSheets {
+    pub fn get(&self, name: &str) -> Option<&SpriteSheet> {
+        self.sheets.get(name)
+    }
+
+    /// Looks up a sheet the game can't run without
+    pub fn expect(&self, name: &str) -> &SpriteSheet {
+        self.get(name).unwrap_or_else(|| panic!("no sprite sheet named {} in {}", name, MANIFEST))
     }
 }
 
@@ -37,15 +59,15 @@ impl Plugin for SpriteSheetPlugin {
     }
 }
 
-pub(crate) fn spawn_sprite<T>(
+pub(crate) fn spawn_sprite(
     commands: &mut Commands,
-    atlas_box: &T,
+    sheet: &SpriteSheet,
     index: usize,
     translation: Vec3
-) -> Entity where T: AtlasBox {
+) -> Entity {
     let sprite = TextureAtlasSprite::new(index);
-    let texture_atlas = atlas_box.atlas();
-    return commands.spawn_bundle(SpriteSheetBundle {
+    let texture_atlas = sheet.atlas.clone();
+    commands.spawn_bundle(SpriteSheetBundle {
         texture_atlas,
         sprite,
         transform: Transform{
@@ -53,7 +75,7 @@ pub(crate) fn spawn_sprite<T>(
             ..Default::default()
         },
         ..Default::default()
-    }).id();
+    }).id()
 }
 
 fn load_sprite_sheets(
@@ -61,36 +83,39 @@ fn load_sprite_sheets(
     assets: Res<AssetServer>,
     mut texture_atlasses: ResMut<Assets<TextureAtlas>>
 ) {
-    let image:Handle<Image> = assets.load("tiles.png");
-    let atlas = TextureAtlas::from_grid_with_padding(
-        image,
-        Vec2::splat(18.),
-        20,
-        9,
-        Vec2::splat(2.0)
-    );
-    let atlas_handle = texture_atlasses.add(atlas);
-    commands.insert_resource(TileAtlas(atlas_handle));
+    let manifest = std::fs::read(MANIFEST).unwrap();
+    let manifest: SpriteManifest = ron::de::from_bytes(&manifest).unwrap();
 
-    let image:Handle<Image> = assets.load("background.png");
-    let atlas = TextureAtlas::from_grid_with_padding(
-        image,
-        Vec2::splat(23.),
-        6,
-        2,
-        Vec2::splat(1.)
-    );
-    let atlas_handle = texture_atlasses.add(atlas);
-    commands.insert_resource(BackgroundAtlas(atlas_handle));
+    let mut sheets = HashMap::default();
+    for (name, source) in manifest.sheets {
+        let atlas = match source {
+            SheetSource::Grid{image, tile_size, columns, rows, padding} => {
+                let image: Handle<Image> = assets.load(image.as_str());
+                TextureAtlas::from_grid_with_padding(image, tile_size, columns, rows, padding)
+            },
+            SheetSource::Tileset(path) => tileset_atlas(&assets, &path)
+        };
+        let tile_size = atlas.textures.first().map_or(Vec2::ZERO, |rect| rect.max - rect.min);
+        let atlas = texture_atlasses.add(atlas);
+        sheets.insert(name, SpriteSheet{atlas, tile_size});
+    }
+    commands.insert_resource(SpriteSheets{sheets});
+}
 
-    let image:Handle<Image> = assets.load("characters.png");
-    let atlas = TextureAtlas::from_grid_with_padding(
-        image,
-        Vec2::splat(CHARACTER_TILE_SIZE),
-        9,
-        3,
-        Vec2::splat(1.)
-    );
-    let atlas_handle = texture_atlasses.add(atlas);
-    commands.insert_resource(CharacterAtlas(atlas_handle));
-}
\ No newline at end of file
+/// Builds an atlas from a `.tsx` tileset relative to the assets folder.
+/// Tiled keeps the gap between tiles as spacing, bevy calls it padding.
+fn tileset_atlas(assets: &AssetServer, path: &str) -> TextureAtlas {
+    let tileset = Loader::new().load_tsx_tileset(format!("assets/{}", path)).unwrap();
+    let image = tileset.image.as_ref().expect("tileset sprite sheets need a single image");
+    //tiled resolves the image next to the tsx, the asset server wants it relative to assets
+    let source = image.source.strip_prefix("assets").unwrap_or(&image.source).to_owned();
+    let columns = tileset.columns as usize;
+    let rows = (tileset.tilecount as usize + columns - 1) / columns;
+    TextureAtlas::from_grid_with_padding(
+        assets.load(source.as_path()),
+        Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
+        columns,
+        rows,
+        Vec2::splat(tileset.spacing as f32)
+    )
+}
@@ -1,14 +1,46 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
 use tiled::*;
 use bevy::render::color::Color;
 
-use crate::{spritesheet::{TileAtlas, spawn_sprite}, TILE_SIZE, geometry::{AxisAlignedBoundingBox, Line, LineHit}};
+use crate::{
+    spritesheet::{SpriteSheets, spawn_sprite},
+    TILE_SIZE,
+    geometry::{AxisAlignedBoundingBox, Line, LineHit, Shape},
+    collisions::CollisionGroups,
+    triggers::Trigger,
+    platforms::MovingPlatform,
+    movement::Interpolated,
+    zones::PhysicsZone,
+    health::Hazard,
+    respawn::is_checkpoint_type};
 
 pub struct TileMapPlugin;
 
+/// Details of the loaded map that aren't tied to any one layer
+pub struct MapInfo {
+    pub properties: Properties,
+    /// world positions of `player_spawn` objects, ordered by object name
+    pub spawns: Vec<Vec2>,
+    /// the area covered by the map's tile grid, in world pixels
+    pub bounds: AxisAlignedBoundingBox
+}
+
+impl MapInfo {
+    /// Where the nth player starts, players past the last spawn point line up beside the first one
+    pub fn spawn_point(&self, index: usize) -> Vec2 {
+        match self.spawns.get(index) {
+            Some(spawn) => *spawn,
+            None => {
+                let first = self.spawns.first().copied().unwrap_or_default();
+                first + Vec2::new(index as f32 * TILE_SIZE, 0.0)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 enum LoadedLayer {
-    SpriteLayer(String, Vec3, Vec<SpriteParams>),
+    SpriteLayer(String, Vec3, Vec<SpriteParams>, Option<CollisionGroups>),
     ObjectLayer(String, Vec3, Vec<ObjectParams>)
     // GroupLayer(String, Vec3, Vec<LoadedLayer>),
     // Ignored
@@ -20,19 +52,52 @@ struct ObjectParams {
     offset: Vec3,
     points: Vec<Vec2>,
     point_size: Vec2,
-    color: Color
+    color: Color,
+    obj_type: String,
+    properties: Properties,
+    trigger: Option<TriggerParams>,
+    platform: Option<PlatformParams>
+}
+
+#[derive(Debug)]
+struct TriggerParams {
+    size: Vec2,
+    round: bool
+}
+
+#[derive(Debug)]
+struct PlatformParams {
+    path: Vec<Vec2>,
+    looped: bool,
+    size: Vec2,
+    speed: f32,
+    groups: CollisionGroups
 }
 
 #[derive(Debug)]
 struct SpriteParams {
     index: usize, 
     offset: Vec3,
-    name: String
+    name: String,
+    surface: Option<TileSurface>,
+    hazard: Option<f32>
+}
+
+/// How a tile behaves underfoot, from the tileset's `friction` and `conveyor` tile properties.
+/// `friction` replaces the body's horizontal ground friction, `conveyor` is a belt speed in pixels per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileSurface {
+    pub friction: Option<f32>,
+    pub conveyor: f32
 }
 
 #[derive(Component)]
 pub struct TileGrid {
-    coords: Vec<Vec2>
+    tiles: HashSet<IVec2>,
+    surfaces: HashMap<IVec2, TileSurface>,
+    /// damage dealt by touching the tile, from the tileset's `hazard` property
+    hazards: HashMap<IVec2, f32>,
+    groups: Option<CollisionGroups>
 }
 
 fn snap_f(f: f32, s: f32) -> f32 {
@@ -43,20 +108,153 @@ fn snap_vector(v: Vec2, s: f32) -> Vec2 {
     Vec2::new(snap_f(v.x, s), snap_f(v.y, s))
 }
 
+/// Converts a tile's world position back to its (x, y) coordinate in the TMX grid
+pub fn to_grid(pos: Vec2) -> IVec2 {
+    IVec2::new((pos.x / TILE_SIZE).round() as i32, (-pos.y / TILE_SIZE).round() as i32)
+}
+
+/// World position of the center of the tile at the given TMX grid coordinate
+pub fn to_world(tile: IVec2) -> Vec2 {
+    Vec2::new(tile.x as f32 * TILE_SIZE, -tile.y as f32 * TILE_SIZE)
+}
+
+pub fn tile_box(tile: IVec2) -> AxisAlignedBoundingBox {
+    AxisAlignedBoundingBox::new(to_world(tile), Vec2::splat(TILE_SIZE))
+}
+
+pub(crate) fn int_property(properties: &Properties, key: &str) -> Option<i32> {
+    match properties.get(key) {
+        Some(PropertyValue::IntValue(i)) => Some(*i),
+        _ => None
+    }
+}
+
+pub fn float_property(properties: &Properties, key: &str) -> Option<f32> {
+    match properties.get(key) {
+        Some(PropertyValue::FloatValue(f)) => Some(*f),
+        Some(PropertyValue::IntValue(i)) => Some(*i as f32),
+        _ => None
+    }
+}
+
+/// Collision groups come from the layer's `collision_layer` and `collision_mask` int properties.
+/// Layers without them fall back to the old rule: a name starting with "ground" is solid world.
+fn layer_groups(name: &str, properties: &Properties) -> Option<CollisionGroups> {
+    let layer = int_property(properties, "collision_layer");
+    let mask = int_property(properties, "collision_mask");
+    match (layer, mask) {
+        (None, None) if name.starts_with("ground") => Some(CollisionGroups::default()),
+        (None, None) => None,
+        _ => Some(property_groups(properties))
+    }
+}
+
+/// Collision groups from `collision_layer` and `collision_mask` int properties, solid world by default
+fn property_groups(properties: &Properties) -> CollisionGroups {
+    CollisionGroups::new(
+        int_property(properties, "collision_layer").map_or(CollisionGroups::WORLD, |l| l as u32),
+        int_property(properties, "collision_mask").map_or(CollisionGroups::ALL, |m| m as u32))
+}
+
 impl TileGrid {
 
+    pub fn new(tiles: HashSet<IVec2>, surfaces: HashMap<IVec2, TileSurface>, hazards: HashMap<IVec2, f32>, groups: Option<CollisionGroups>) -> Self {
+        TileGrid{tiles, surfaces, hazards, groups}
+    }
+
+    pub fn groups(&self) -> Option<CollisionGroups> {
+        self.groups
+    }
+
+    /// Whether tiles in this layer block a collidable in the given groups
+    pub fn collides_with(&self, groups: &CollisionGroups) -> bool {
+        self.groups.map_or(false, |g| g.interacts(groups))
+    }
+
+    pub fn contains(&self, tile: IVec2) -> bool {
+        self.tiles.contains(&tile)
+    }
+
+    pub fn surface(&self, tile: IVec2) -> Option<TileSurface> {
+        self.surfaces.get(&tile).copied()
+    }
+
+    /// A hazard tile overlapping the area and its damage, if any
+    pub fn hazard(&self, area: &AxisAlignedBoundingBox) -> Option<(IVec2, f32)> {
+        if self.hazards.is_empty() {
+            return None;
+        }
+        let a = to_grid(area.min());
+        let b = to_grid(area.max());
+        for y in b.y..=a.y {
+            for x in a.x..=b.x {
+                let tile = IVec2::new(x, y);
+                if let Some(damage) = self.hazards.get(&tile) {
+                    if area.intersects(&tile_box(tile)) {
+                        return Some((tile, *damage));
+                    }
+                }
+            }
+        }
+        None
+    }
+
     pub fn cast_axis_ray(&self, origin: Vec2, vector: Vec2) -> Option<LineHit> {
         //dodgy hack, assume we're never going to shift more than one tile at a time due to speed of light constraints
-        let pos = snap_vector(origin + vector, TILE_SIZE);
+        let tile = to_grid(snap_vector(origin + vector, TILE_SIZE));
+        if !self.contains(tile) {
+            return None;
+        }
+
+        tile_box(tile)
+            .intersection(Line::new(origin, vector))
+            .map(|hit| hit.with_tile(tile))
+    }
+
+    /// Sweeps a box of the given size along the line and returns the first tile it strikes.
+    /// The hit position is the center of the box at the moment of contact, and a zero size
+    /// casts a plain ray of any length or direction.
+    pub fn cast_box(&self, line: Line, size: Vec2) -> Option<LineHit> {
+        let start = line.origin();
+        let end = start + line.vector();
+        let reach = size / 2.;
+        let a = to_grid(start.min(end) - reach);
+        let b = to_grid(start.max(end) + reach);
+        let expanded = Vec2::splat(TILE_SIZE) + size;
 
-        let tile = self.coords.iter().find(|&i| i.eq(&pos));
-        // if tile.is_some() {
-        //     println!("Tile at: {}\n\torigin {}\n\tvector {}", pos, origin, vector);
-        // } else {
-        //     println!("no tile at: {}\n\torigin {}\n\tvector {}", pos, origin, vector);
-        // }
+        let mut closest: Option<LineHit> = None;
+        //grid y runs downwards, so the corners swap on that axis
+        for y in (b.y - 1)..=(a.y + 1) {
+            for x in (a.x - 1)..=(b.x + 1) {
+                let tile = IVec2::new(x, y);
+                if !self.contains(tile) {
+                    continue;
+                }
+                let hit = AxisAlignedBoundingBox::new(to_world(tile), expanded).intersection(line);
+                if let Some(hit) = hit {
+                    if closest.map_or(true, |c| hit.t() < c.t()) {
+                        closest = Some(hit.with_tile(tile));
+                    }
+                }
+            }
+        }
+        closest
+    }
 
-        return tile.and_then(|c| AxisAlignedBoundingBox::new(*c, Vec2::splat(TILE_SIZE)).intersection(Line::new(origin, vector)));
+    /// First tile the shape overlaps, if any
+    pub fn overlaps(&self, shape: &Shape) -> Option<IVec2> {
+        let bounds = shape.bounds();
+        let a = to_grid(bounds.min());
+        let b = to_grid(bounds.max());
+        for y in b.y..=a.y {
+            for x in a.x..=b.x {
+                let tile = IVec2::new(x, y);
+                if self.contains(tile) && shape.intersects_aabb(&tile_box(tile)) {
+                    return Some(tile);
+                }
+            }
+        }
+        None
     }
 }
 
@@ -70,10 +268,12 @@ impl Plugin for TileMapPlugin {
 
 fn load_tilemap(
     mut commands: Commands, 
-    tile_map: Res<TileAtlas>,
+    sheets: Res<SpriteSheets>,
 ) {
     let mut loader = Loader::new();
     let map = loader.load_tmx_map("assets/world.tmx").unwrap();
+    let tile_sheet = sheets.expect("tiles");
+    let mut spawns = Vec::new();
 
     let map_entity = commands.spawn()
         .insert(Transform::default())
@@ -84,7 +284,7 @@ fn load_tilemap(
     let layers = load_layers(map.layers(), 0.0);
     for layer in layers {
         match layer {
-            LoadedLayer::SpriteLayer(name, offset, sprite_params) => {
+            LoadedLayer::SpriteLayer(name, offset, sprite_params, groups) => {
                 let layer_entity = commands.spawn()
                     .insert(Name::new(name))
                     .insert(Transform{
@@ -92,11 +292,13 @@ fn load_tilemap(
                         ..Default::default()
                     })
                     .insert(GlobalTransform::default()).id();
-                let mut coords = Vec::new();
+                let mut tiles = HashSet::default();
+                let mut surfaces = HashMap::default();
+                let mut hazards = HashMap::default();
                 for params in sprite_params {
                     let sprite = spawn_sprite(
                         &mut commands, 
-                        &tile_map as &TileAtlas, 
+                        tile_sheet, 
                         params.index, 
                         params.offset
                     );
@@ -104,20 +306,89 @@ fn load_tilemap(
                         .insert(Name::new(params.name))
                         .id();
                     commands.entity(layer_entity).add_child(named_sprite);
-                    coords.push(Vec2::new(params.offset.x, params.offset.y));
+                    let tile = to_grid(Vec2::new(params.offset.x, params.offset.y));
+                    tiles.insert(tile);
+                    if let Some(surface) = params.surface {
+                        surfaces.insert(tile, surface);
+                    }
+                    if let Some(damage) = params.hazard {
+                        hazards.insert(tile, damage);
+                    }
                 }
-                commands.entity(layer_entity).insert(TileGrid{coords});
+                commands.entity(layer_entity).insert(TileGrid::new(tiles, surfaces, hazards, groups));
                 commands.entity(map_entity).add_child(layer_entity);
             },
             LoadedLayer::ObjectLayer(name, offset, objects) => {
+                let layer_origin = Vec2::new(offset.x - TILE_SIZE/2.0, offset.y + TILE_SIZE/2.0);
                 let layer_entity = commands.spawn()
                     .insert(Name::new(name))
                     .insert(Transform{
-                        translation: Vec3::new(offset.x - TILE_SIZE/2.0, offset.y + TILE_SIZE/2.0, offset.z),
+                        translation: layer_origin.extend(offset.z),
                         ..Default::default()
                     })
                     .insert(GlobalTransform::default()).id();
                 for obj in objects {
+                    if obj.obj_type == "player_spawn" {
+                        spawns.push((obj.name.to_owned(), layer_origin + obj.offset.truncate()));
+                    }
+                    if let Some(trigger) = obj.trigger {
+                        //tiled positions rects and ellipses by their top left corner
+                        let center = obj.offset.truncate() + Vec2::new(trigger.size.x, -trigger.size.y) / 2.0;
+                        let zone = PhysicsZone::from_object(&obj.obj_type, &obj.properties);
+                        let hazard = Hazard::from_object(&obj.obj_type, &obj.properties);
+                        let trigger_ent = commands.spawn()
+                            .insert(GlobalTransform::default())
+                            .insert(Transform{
+                                translation: center.extend(0.0),
+                                ..Default::default()
+                            })
+                            .insert(Name::new(obj.name.to_owned()))
+                            .insert(Trigger::new(
+                                obj.obj_type,
+                                int_property(&obj.properties, "collision_mask").map_or(CollisionGroups::ALL, |m| m as u32),
+                                layer_origin + center,
+                                trigger.size,
+                                trigger.round))
+                            .id();
+                        if let Some(zone) = zone {
+                            commands.entity(trigger_ent).insert(zone);
+                        }
+                        if let Some(hazard) = hazard {
+                            commands.entity(trigger_ent).insert(hazard);
+                        }
+                        commands.entity(layer_entity).add_child(trigger_ent);
+                        continue;
+                    }
+                    if let Some(platform) = obj.platform {
+                        //platforms move through the world, so they hang off the map rather than the layer
+                        let path: Vec<Vec2> = platform.path.iter()
+                            .map(|p| layer_origin + obj.offset.truncate() + *p)
+                            .collect();
+                        //a platform needs somewhere to start, objects with no points are skipped
+                        let start = match path.first() {
+                            Some(start) => *start,
+                            None => continue
+                        };
+                        let platform_ent = commands
+                            .spawn_bundle(SpriteBundle {
+                                sprite: Sprite {
+                                    color: Color::rgb(0.6, 0.4, 0.2),
+                                    custom_size: Some(platform.size),
+                                    ..Default::default()
+                                },
+                                transform: Transform {
+                                    translation: start.extend(offset.z),
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            })
+                            .insert(Name::new(obj.name.to_owned()))
+                            .insert(Interpolated::new(start))
+                            .insert(MovingPlatform::new(path, platform.looped, platform.size, platform.speed, platform.groups))
+                            .id();
+                        commands.entity(map_entity).add_child(platform_ent);
+                        continue;
+                    }
                     let obj_ent = commands.spawn()
                         .insert(GlobalTransform::default())
                         .insert(Transform{
@@ -149,6 +420,21 @@ fn load_tilemap(
             }
         }
     }
+
+    spawns.sort_by(|(a, _), (b, _)| a.cmp(b));
+    commands.insert_resource(MapInfo{
+        properties: map.properties.clone(),
+        spawns: spawns.into_iter().map(|(_, p)| p).collect(),
+        bounds: map_bounds(&map)
+    });
+}
+
+/// Tile centres sit on the grid, so the map's top left corner is half a tile up and left of the origin
+fn map_bounds(map: &Map) -> AxisAlignedBoundingBox {
+    let tile = Vec2::new(map.tile_width as f32, map.tile_height as f32);
+    let size = Vec2::new(map.width as f32, map.height as f32) * tile;
+    let top_left = Vec2::new(-tile.x, tile.y) / 2.0;
+    AxisAlignedBoundingBox::new(top_left + Vec2::new(size.x, -size.y) / 2.0, size)
 }
 
 fn load_layers<'a>(layers: impl Iterator<Item = Layer<'a>>, z: f32) -> Vec<LoadedLayer>{
@@ -161,7 +447,8 @@ fn load_layers<'a>(layers: impl Iterator<Item = Layer<'a>>, z: f32) -> Vec<Loade
         match layer.layer_type() {
             LayerType::TileLayer(TileLayer::Finite(data)) => {
                 let params = finite_tile_layer(data);
-                loaded_layers.push(LoadedLayer::SpriteLayer(name, offset, params));
+                let groups = layer_groups(&name, &layer.properties);
+                loaded_layers.push(LoadedLayer::SpriteLayer(name, offset, params, groups));
             },
             LayerType::ObjectLayer(data) => {
                 let params = object_layer(data);
@@ -172,23 +459,59 @@ fn load_layers<'a>(layers: impl Iterator<Item = Layer<'a>>, z: f32) -> Vec<Loade
             }
         }
     }
-    return loaded_layers;
+    loaded_layers
 }
 
 fn to_vec(points: &Vec<(f32, f32)>) -> Vec<Vec2> {
     let mut mapped = Vec::new();
     for point in points {
-        mapped.push(Vec2::new(point.0, point.1*-1.0));
+        mapped.push(Vec2::new(point.0, -point.1));
     }
-    return mapped;
+    mapped
+}
+
+/// Platform size is given in tiles by `width` and `height`, speed in pixels per second.
+/// Collision groups come from the same properties as tile layers.
+fn platform_params(points: &Vec<(f32, f32)>, looped: bool, properties: &Properties) -> PlatformParams {
+    let width = int_property(properties, "width").unwrap_or(3);
+    let height = int_property(properties, "height").unwrap_or(1);
+    PlatformParams {
+        path: to_vec(points),
+        looped,
+        size: Vec2::new(width as f32, height as f32) * TILE_SIZE,
+        speed: float_property(properties, "speed").unwrap_or(2.0 * TILE_SIZE),
+        groups: property_groups(properties)
+    }
+}
+
+/// Objects with a `trigger` bool property set become invisible sensor volumes, as do physics zones
+fn is_trigger(obj_type: &str, properties: &Properties) -> bool {
+    matches!(properties.get("trigger"), Some(PropertyValue::BoolValue(true)))
+        || PhysicsZone::is_zone_type(obj_type)
+        || Hazard::is_hazard_type(obj_type)
+        || is_checkpoint_type(obj_type)
 }
 
 fn object_layer(data: ObjectLayer) -> Vec<ObjectParams> {
     let mut meshes = Vec::new();
     
     for obj in data.objects() {
-        let offset = Vec3::new(obj.x, obj.y*-1.0, 0.0);
+        let offset = Vec3::new(obj.x, -obj.y, 0.0);
         let name = obj.name.to_owned();
+        let obj_type = obj.obj_type.to_owned();
+        let properties = obj.properties.clone();
+        let trigger = match &obj.shape {
+            tiled::ObjectShape::Rect { width, height } if is_trigger(&obj_type, &properties) =>
+                Some(TriggerParams{size: Vec2::new(*width, *height), round: false}),
+            tiled::ObjectShape::Ellipse { width, height } if is_trigger(&obj_type, &properties) =>
+                Some(TriggerParams{size: Vec2::new(*width, *height), round: true}),
+            _ => None
+        };
+        let platform = match &obj.shape {
+            tiled::ObjectShape::Polyline { points } if obj_type == "platform" => Some(platform_params(points, false, &properties)),
+            tiled::ObjectShape::Polygon { points } if obj_type == "platform" => Some(platform_params(points, true, &properties)),
+            _ => None
+        };
         let (points, size, color) = match &obj.shape {
             tiled::ObjectShape::Point(_,_) => 
                 (vec![Vec2::new(0.0, 0.0)], Vec2::new(TILE_SIZE, TILE_SIZE), Color::rgba(0.0,1.0,0.0,0.8)),
@@ -198,14 +521,21 @@ fn object_layer(data: ObjectLayer) -> Vec<ObjectParams> {
                 (to_vec(points), Vec2::new(TILE_SIZE/4.0, TILE_SIZE/4.0), Color::rgba(1.0, 1.0, 0.0, 0.8)),
             tiled::ObjectShape::Ellipse { width, height } => 
                 (vec![Vec2::new(0.0, 0.0)], Vec2::new(*width, *height), Color::rgba(0.0,0.0,1.0,0.8)),
-            // tiled::ObjectShape::Polyline { points } => {
-            //     println!("\tpolyLine {},{} \n\t\t{:?}", x, y, points)
-            // },
-            _ => (vec![], Vec2::new(0.0,0.0), Color::BLACK)
+            tiled::ObjectShape::Polyline { points } => 
+                (to_vec(points), Vec2::new(TILE_SIZE/4.0, TILE_SIZE/4.0), Color::rgba(0.0, 1.0, 1.0, 0.8)),
         };
-        meshes.push(ObjectParams{name, offset, points, point_size: size, color});
+        meshes.push(ObjectParams{name, offset, points, point_size: size, color, obj_type, properties, trigger, platform});
     }
-    return meshes;
+    meshes
+}
+
+fn tile_surface(properties: &Properties) -> Option<TileSurface> {
+    let friction = float_property(properties, "friction");
+    let conveyor = float_property(properties, "conveyor");
+    if friction.is_none() && conveyor.is_none() {
+        return None;
+    }
+    Some(TileSurface{friction, conveyor: conveyor.unwrap_or(0.0)})
 }
 
 fn finite_tile_layer(data: FiniteTileLayer) -> Vec<SpriteParams> {
@@ -214,14 +544,18 @@ fn finite_tile_layer(data: FiniteTileLayer) -> Vec<SpriteParams> {
         for x in 0..(data.width()) {
             let tx = x as f32 * TILE_SIZE;
             let ty = y as f32 * -TILE_SIZE;
-            data.get_tile(x as i32, y as i32).map(|tile_index| {
+            if let Some(tile_index) = data.get_tile(x as i32, y as i32) {
+                let surface = tile_index.get_tile().and_then(|tile| tile_surface(&tile.properties));
+                let hazard = tile_index.get_tile().and_then(|tile| float_property(&tile.properties, "hazard"));
                 tiles.push(SpriteParams{
                     name: format!("{},{}", x, y),
                     index: tile_index.id().try_into().unwrap(), 
-                    offset: Vec3::new(tx, ty, 0.0)
+                    offset: Vec3::new(tx, ty, 0.0),
+                    surface,
+                    hazard
                 });
-            });
+            }
         }
     }
-    return tiles;
+    tiles
 }
\ No newline at end of file
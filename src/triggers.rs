@@ -0,0 +1,104 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+  collisions::Collidable,
+  geometry::{AxisAlignedBoundingBox, Shape, Circle, Capsule},
+  movement::PHYSICS_STAGE};
+
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .add_event::<TriggerEntered>()
+        .add_event::<TriggerExited>()
+        .add_system_to_stage(PHYSICS_STAGE, trigger_zones.label("triggers").after("movement"));
+  }
+}
+
+/// Fired when a collidable moves into a trigger zone
+pub struct TriggerEntered {
+  pub trigger: Entity,
+  pub entity: Entity
+}
+
+/// Fired when a collidable leaves a trigger zone, or the zone notices it has been despawned
+pub struct TriggerExited {
+  pub trigger: Entity,
+  pub entity: Entity
+}
+
+/// Invisible sensor volume loaded from a Tiled rect or ellipse object.
+/// `kind` is the object's type, so gameplay systems can pick out the triggers they care about.
+#[derive(Component)]
+pub struct Trigger {
+  pub kind: String,
+  center: Vec2,
+  size: Vec2,
+  round: bool,
+  mask: u32,
+  occupants: HashSet<Entity>
+}
+
+impl Trigger {
+  /// `mask` picks which collidables can set the trigger off, by their collision layer
+  pub fn new(kind: String, mask: u32, center: Vec2, size: Vec2, round: bool) -> Self {
+    Trigger { kind, center, size, round, mask, occupants: HashSet::default() }
+  }
+
+  /// Ellipses are approximated by the capsule that fits inside them
+  pub fn shape(&self) -> Shape {
+    let (w, h) = (self.size.x, self.size.y);
+    if !self.round {
+      Shape::Box(AxisAlignedBoundingBox::new(self.center, self.size))
+    } else if w == h {
+      Shape::Circle(Circle::new(self.center, w / 2.))
+    } else if h > w {
+      Shape::Capsule(Capsule::vertical(self.center, w, h))
+    } else {
+      let half = Vec2::new((w - h) / 2., 0.);
+      Shape::Capsule(Capsule::new(self.center - half, self.center + half, h / 2.))
+    }
+  }
+
+  pub fn center(&self) -> Vec2 {
+    self.center
+  }
+
+  pub fn contains(&self, entity: Entity) -> bool {
+    self.occupants.contains(&entity)
+  }
+
+  /// Everything inside the trigger as of the last physics step
+  pub fn occupants(&self) -> impl Iterator<Item = Entity> + '_ {
+    self.occupants.iter().copied()
+  }
+}
+
+fn trigger_zones(
+  mut triggers: Query<(Entity, &mut Trigger)>,
+  collidables: Query<(Entity, &Transform, &Collidable)>,
+  mut entered: EventWriter<TriggerEntered>,
+  mut exited: EventWriter<TriggerExited>
+) {
+  for (trigger_entity, mut trigger) in triggers.iter_mut() {
+    let zone = trigger.shape();
+    let mut inside = HashSet::default();
+    for (entity, transform, collidable) in collidables.iter() {
+      if collidable.groups.layer & trigger.mask == 0 {
+        continue;
+      }
+      if zone.intersects(&collidable.shape_at(transform.translation.truncate())) {
+        inside.insert(entity);
+      }
+    }
+
+    for &entity in inside.difference(&trigger.occupants) {
+      entered.send(TriggerEntered{trigger: trigger_entity, entity});
+    }
+    for &entity in trigger.occupants.difference(&inside) {
+      exited.send(TriggerExited{trigger: trigger_entity, entity});
+    }
+    trigger.occupants = inside;
+  }
+}
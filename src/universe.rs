@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+
+use crate::{movement::PhysicalUniverse, tilemap::MapInfo, ron_asset::{RonAsset, HotReload, HotReloadApp}};
+
+pub struct UniversePlugin;
+
+impl Plugin for UniversePlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .add_hot_reload::<PhysicalUniverse>("physics.universe.ron")
+        .add_system(apply_universe);
+  }
+}
+
+impl RonAsset for PhysicalUniverse {
+  const EXTENSIONS: &'static [&'static str] = &["universe.ron"];
+}
+
+fn apply_universe(
+  mut events: EventReader<AssetEvent<PhysicalUniverse>>,
+  file: Res<HotReload<PhysicalUniverse>>,
+  loaded: Res<Assets<PhysicalUniverse>>,
+  map: Option<Res<MapInfo>>,
+  mut universe: ResMut<PhysicalUniverse>
+) {
+  if let Some(settings) = file.reloaded(&mut events, &loaded) {
+    let mut settings = settings.clone();
+    if let Some(map) = &map {
+      settings.apply_overrides(&map.properties);
+    }
+    *universe = settings;
+  } else if let Some(map) = map.filter(|map| map.is_added()) {
+    //a freshly loaded map tweaks whatever settings are already in place
+    universe.apply_overrides(&map.properties);
+  }
+}
@@ -0,0 +1,128 @@
+use bevy::{prelude::*, utils::HashMap};
+use tiled::Properties;
+
+use crate::{
+  movement::{Accelleration, ForceKind, PHYSICS_STAGE},
+  tilemap::float_property,
+  triggers::Trigger};
+
+pub struct ZonePlugin;
+
+impl Plugin for ZonePlugin {
+  fn build(&self, app:&mut App) {
+      app
+        .add_system_to_stage(PHYSICS_STAGE, apply_zones.label("zones").after("triggers"));
+  }
+}
+
+/// Area of a level that changes physics for every body inside it.
+/// Loaded from trigger objects of type `water`, `low_gravity` or `wind`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PhysicsZone {
+  pub force_kind: ForceKind,
+  pub force: Vec2,
+  /// friction added on both axes
+  pub drag: f32,
+  pub gravity_scale: f32,
+  pub water: bool
+}
+
+impl PhysicsZone {
+  pub fn is_zone_type(obj_type: &str) -> bool {
+    matches!(obj_type, "water" | "low_gravity" | "wind")
+  }
+
+  /// Defaults per zone type, each can be overridden with the `force_x`, `force_y`,
+  /// `drag` and `gravity_scale` object properties
+  pub fn from_object(obj_type: &str, properties: &Properties) -> Option<Self> {
+    let zone = match obj_type {
+      "water" => PhysicsZone {
+        force_kind: ForceKind::ZoneBuoyancy,
+        force: Vec2::new(0., 600.),
+        drag: 6.,
+        gravity_scale: 1.,
+        water: true
+      },
+      "low_gravity" => PhysicsZone {
+        force_kind: ForceKind::ZoneBuoyancy,
+        force: Vec2::ZERO,
+        drag: 0.,
+        gravity_scale: 0.3,
+        water: false
+      },
+      "wind" => PhysicsZone {
+        force_kind: ForceKind::ZoneWind,
+        force: Vec2::new(300., 0.),
+        drag: 0.,
+        gravity_scale: 1.,
+        water: false
+      },
+      _ => return None
+    };
+    Some(PhysicsZone {
+      force: Vec2::new(
+        float_property(properties, "force_x").unwrap_or(zone.force.x),
+        float_property(properties, "force_y").unwrap_or(zone.force.y)),
+      drag: float_property(properties, "drag").unwrap_or(zone.drag),
+      gravity_scale: float_property(properties, "gravity_scale").unwrap_or(zone.gravity_scale),
+      ..zone
+    })
+  }
+}
+
+/// The combined effect of every zone a body is currently in
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct InZone {
+  pub drag: f32,
+  pub gravity_scale: f32,
+  pub swimming: bool
+}
+
+const ZONE_FORCES: [ForceKind; 2] = [ForceKind::ZoneWind, ForceKind::ZoneBuoyancy];
+
+fn apply_zones(
+  mut commands: Commands,
+  zones: Query<(&Trigger, &PhysicsZone)>,
+  mut bodies: Query<(&mut Accelleration, Option<&InZone>)>,
+  zoned: Query<Entity, With<InZone>>
+) {
+  //only bodies inside a zone, or that were last step, are touched
+  let mut effects: HashMap<Entity, (InZone, [Vec2; ZONE_FORCES.len()])> = HashMap::default();
+  for (trigger, zone) in zones.iter() {
+    for entity in trigger.occupants() {
+      let (effect, forces) = effects.entry(entity)
+        .or_insert((InZone { drag: 0., gravity_scale: 1., swimming: false }, [Vec2::ZERO; ZONE_FORCES.len()]));
+      effect.drag += zone.drag;
+      effect.gravity_scale *= zone.gravity_scale;
+      effect.swimming |= zone.water;
+      if let Some(i) = ZONE_FORCES.iter().position(|k| *k == zone.force_kind) {
+        forces[i] += zone.force;
+      }
+    }
+  }
+
+  for (entity, (effect, forces)) in effects.iter() {
+    if let Ok((mut accel, current)) = bodies.get_mut(*entity) {
+      //zone forces that no longer apply are removed rather than zeroed, so leaving is clean
+      for (kind, force) in ZONE_FORCES.iter().zip(forces) {
+        if *force == Vec2::ZERO {
+          accel.remove_force(*kind);
+        } else {
+          accel.set_force(*kind, *force);
+        }
+      }
+      if current != Some(effect) {
+        commands.entity(*entity).insert(*effect);
+      }
+    }
+  }
+
+  for entity in zoned.iter().filter(|entity| !effects.contains_key(entity)) {
+    if let Ok((mut accel, _)) = bodies.get_mut(entity) {
+      for kind in ZONE_FORCES {
+        accel.remove_force(kind);
+      }
+    }
+    commands.entity(entity).remove::<InZone>();
+  }
+}